@@ -1,7 +1,7 @@
 import { IterateSpecNames, IterateSpec } from "./gen/Iterate.js";
 import { MethodSpecNames, MethodSpec } from "./gen/Methods.js";
 import { NotifyCSpec } from "./gen/NotifyC.js";
-import type { Color, Item, RejectMessage, Stroke, Transform } from "./gen/Types.js";
+import type { BatchResponse, Color, Item, NotifyCBatch, RejectMessage, Stroke, Transform } from "./gen/Types.js";
 import { AsyncIter } from "./util/AsyncIter.js";
 
 export type Id<T> = { [K in keyof T]: T[K] };
@@ -131,4 +131,14 @@ export type IterateResponse = IResponse<IName>;
 
 export type MsgSend = MethodCall | IterateCall;
 
-export type MsgRecv = MethodResponse | NotifyC | IterateResponse | RejectPayload;
\ No newline at end of file
+export type BatchResponsePayload = {
+	protocol: "Batch-Response",
+	messages: MsgRecv[],
+} & Omit<BatchResponse, "messages">;
+
+export type NotifyCBatchPayload = {
+	protocol: "Notify-C-Batch",
+	notifications: ({ name: NCName } & NCArgs)[],
+} & Omit<NotifyCBatch, "notifications">;
+
+export type MsgRecv = MethodResponse | NotifyC | IterateResponse | RejectPayload | BatchResponsePayload | NotifyCBatchPayload;
\ No newline at end of file
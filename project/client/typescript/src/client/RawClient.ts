@@ -108,6 +108,12 @@ export class RawClient {
 			case "Reject": {
 				logger.error("Received Reject message: ", msg);
 			} break;
+			case "Batch-Response": {
+				for (const message of msg.messages) this.handleMessageObject(message);
+			} break;
+			case "Notify-C-Batch": {
+				for (const { name, ...args } of msg.notifications) this.handleNotifyC(name, args);
+			} break;
 			default: {
 				logger.error("Unknown message type: ", msg);
 			}
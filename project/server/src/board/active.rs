@@ -1,11 +1,13 @@
 #[path = "./active_helpers.rs"]
 mod active_helpers;
+#[path = "./batch_impls.rs"]
+mod batch_impls;
 #[path = "./iterate_impls.rs"]
 mod iterate_impls;
 #[path = "./method_impls.rs"]
 mod method_impls;
 
-use std::{sync::Arc, time::Duration};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use log::error;
 use scc::HashMap as AsyncHashMap;
@@ -20,12 +22,12 @@ use crate::{
     message::{
         self as m,
         iterate::{GetActivePath, IterateHandle},
-        notify_c::ClientJoined,
+        notify_c::{ClientJoined, SelectionItemsRemoved},
         ClientID, ClientInfo, ConnectionInfo, ItemID, MsgRecv, PathID, SessionID,
     },
 };
 
-use super::{BoardHandle, BoardMessage};
+use super::{BoardHandle, BoardManager, BoardMessage};
 
 static PATH_FLUSH_TIME: Duration = Duration::from_millis(750);
 
@@ -36,9 +38,11 @@ struct ActivePath {
     listeners: Vec<IterateHandle<GetActivePath>>,
     stroke: Stroke,
     last_flush: Instant,
+    /// Whether to replace the path with a neat shape when it is finished
+    recognize_shapes: bool,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct SelectionState {
     items: std::collections::BTreeMap<ItemID, Transform>,
     own_transform: Transform,
@@ -50,6 +54,7 @@ struct ClientState {
     handle: Option<ClientHandle>,
     active_paths: Vec<PathID>,
     selection: SelectionState,
+    latency: Option<Duration>,
 }
 
 struct Board {
@@ -58,10 +63,21 @@ struct Board {
     canvas: Arc<ActiveCanvas>,
     selected_items: AsyncHashMap<ItemID, Option<ClientID>>,
     active_paths: AsyncHashMap<PathID, ActivePath>,
+    /// Where uploaded files are read from and generated images are stored
+    media_root: PathBuf,
+    /// Used to check links to other boards
+    boards: &'static BoardManager,
+    /// The tolerance finished paths are simplified with
+    path_tolerance: f64,
 }
 
 impl Board {
-    fn new_from_canvas(canvas: Arc<ActiveCanvas>) -> Self {
+    fn new_from_canvas(
+        canvas: Arc<ActiveCanvas>,
+        media_root: PathBuf,
+        boards: &'static BoardManager,
+        path_tolerance: f64,
+    ) -> Self {
         let selected_items = AsyncHashMap::default();
 
         for id in canvas.get_item_ids_sync().unwrap() {
@@ -74,6 +90,9 @@ impl Board {
             canvas,
             selected_items,
             active_paths: Default::default(),
+            media_root,
+            boards,
+            path_tolerance,
         }
     }
 
@@ -105,9 +124,12 @@ impl Board {
                 client.get_mut().handle = Some(handle);
             }
             BoardMessage::ClientDisconnected(id) => {
-                let mut _info = self.get_client(&id).await;
-                let info = _info.get_mut();
-                info.handle.take();
+                // An atomic batch being undone would otherwise hand the client's items back
+                let _guard = self.canvas.batch_lock().read().await;
+                self.handle_client_disconnected(id).await;
+            }
+            BoardMessage::ClientLatency(id, latency) => {
+                self.get_client(&id).await.get_mut().latency = Some(latency);
             }
             BoardMessage::SessionRequest(info, reply) => {
                 self.handle_session_request(info, reply).await
@@ -115,6 +137,48 @@ impl Board {
         }
     }
 
+    /// Release everything the client was holding so that other clients can use it
+    async fn handle_client_disconnected(&self, id: ClientID) {
+        let (selection, paths) = {
+            let mut client = self.get_client(&id).await;
+            let client = client.get_mut();
+            client.handle = None;
+            (
+                std::mem::take(&mut client.selection),
+                std::mem::take(&mut client.active_paths),
+            )
+        };
+
+        let mut released = Vec::with_capacity(selection.items.len());
+        for item_id in selection.items.into_keys() {
+            if let Some(mut entry) = self.selected_items.get_async(&item_id).await {
+                if *entry.get() == Some(id) {
+                    *entry.get_mut() = None;
+                }
+            }
+            // Items keep the location they were last placed at
+            if let Some((item, revision)) = self.canvas.get_item_revision(item_id).await {
+                released.push((item_id, item.location(), revision));
+            }
+        }
+
+        for path_id in paths {
+            if let Some((_, path)) = self.active_paths.remove_async(&path_id).await {
+                for handle in path.listeners {
+                    handle.finalize();
+                }
+            }
+        }
+
+        if !released.is_empty() {
+            self.send_notify_c(SelectionItemsRemoved {
+                id,
+                items: released,
+            })
+            .await;
+        }
+    }
+
     async fn handle_session_request(
         &self,
         info: ClientInfo,
@@ -127,6 +191,7 @@ impl Board {
             handle: None,
             active_paths: Default::default(),
             selection: Default::default(),
+            latency: None,
         };
 
         self.clients
@@ -152,13 +217,26 @@ impl Board {
 
     async fn handle_client_message(&self, id: ClientID, msg: MsgRecv) {
         match msg {
-            MsgRecv::Method(method) => self.handle_method(id, method).await,
-            MsgRecv::Iterate(iterate) => self.handle_iterate(id, iterate).await,
+            MsgRecv::Method(method) => {
+                let _guard = self.canvas.batch_lock().read().await;
+                self.handle_method(id, method).await;
+            }
+            MsgRecv::Iterate(iterate) => {
+                let _guard = self.canvas.batch_lock().read().await;
+                self.handle_iterate(id, iterate).await
+            }
+            MsgRecv::Batch(batch) => self.handle_batch(id, batch).await,
         }
     }
 }
 
-pub fn from_canvas(canvas: Arc<ActiveCanvas>, tasks: usize) -> BoardHandle {
-    let board = Board::new_from_canvas(canvas);
+pub fn from_canvas(
+    canvas: Arc<ActiveCanvas>,
+    tasks: usize,
+    media_root: PathBuf,
+    boards: &'static BoardManager,
+    path_tolerance: f64,
+) -> BoardHandle {
+    let board = Board::new_from_canvas(canvas, media_root, boards, path_tolerance);
     board.launch(tasks)
 }
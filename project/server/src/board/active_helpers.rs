@@ -1,41 +1,44 @@
+use std::collections::BTreeSet;
+
 use scc::hash_map::OccupiedEntry;
 
 use crate::{
+    canvas::{
+        active::ItemRef,
+        item::{ConnectorItem, FrameItem, ImageItem, LinkItem},
+        Item, Transform,
+    },
     client::{ClientHandle, MessagePayload},
     message::{
         iterate::{IterateHandle, IterateType},
         method::{MethodHandle, MethodType},
-        notify_c::NotifyCType,
+        notify_c::{NotifyCType, SingleItemEdited},
         reject::{
             helpers::{non_existent_id, resource_not_owned},
             RejectReason,
         },
-        ClientID, ItemID,
+        ClientID, ItemID, MsgSend,
     },
+    upload::media_info,
 };
 
-use super::{Board, ClientState};
+use super::{
+    batch_impls::{batch_handle, buffer_notify, in_batch},
+    Board, ClientState,
+};
 
 /// Common interface of method and iterate handles
 trait Handle {
-    fn send_error(self, reason: RejectReason);
     fn send_warn(&self, reason: RejectReason);
 }
 
 impl<T: MethodType> Handle for MethodHandle<T> {
-    fn send_error(self, reason: RejectReason) {
-        self.error(reason)
-    }
-
     fn send_warn(&self, reason: RejectReason) {
         self.warn(reason)
     }
 }
 
 impl<T: IterateType> Handle for IterateHandle<T> {
-    fn send_error(self, reason: RejectReason) {
-        self.error(reason)
-    }
     fn send_warn(&self, reason: RejectReason) {
         self.warn(reason)
     }
@@ -103,12 +106,223 @@ impl Board {
     }
 
     pub async fn get_handle(&self, id: &ClientID) -> Option<ClientHandle> {
+        if let Some(handle) = batch_handle(id) {
+            return Some(handle);
+        }
         self.clients.get_async(id).await?.get().handle.clone()
     }
 
+    /// Get an item as clients currently see it, including the movement of any selection holding it
+    pub async fn displayed_item(&self, item_id: ItemID) -> Option<Item> {
+        let mut item = self.canvas.get_item(item_id).await?;
+        let owner = *self.selected_items.get_async(&item_id).await?.get();
+        if let Some(owner) = owner {
+            if let Some(client) = self.clients.get_async(&owner).await {
+                let selection = &client.get().selection;
+                if let Some(sit) = selection.items.get(&item_id) {
+                    item.transform_by(&selection.own_transform.compose(sit));
+                }
+            }
+        }
+        Some(item)
+    }
+
+    /// Move the ends of a connector onto its items, returning false if either is missing
+    pub async fn attach_connector(&self, connector: &mut ConnectorItem) -> bool {
+        let source = self.displayed_item(connector.source).await;
+        let target = self.displayed_item(connector.target).await;
+        let (Some(source), Some(target)) = (source, target) else {
+            return false;
+        };
+        connector.attach(&source, &target);
+        true
+    }
+
+    /// Check an item's colors and styles and what it refers to outside the board, filling in the details kept by the server
+    ///
+    /// This may read from the canvas, so no item on it should be held
+    pub async fn validate_item(&self, item: &mut Item) -> Result<(), RejectReason> {
+        item.normalize_colors()?;
+        if let Some(stroke) = item.stroke() {
+            stroke.check()?;
+        }
+        if let Some(fill) = item.fill() {
+            fill.check()?;
+        }
+        match item {
+            Item::Link(LinkItem {
+                target: Some(target),
+                ..
+            }) => self.boards.resolve_link(target).await,
+            Item::Image(image) => self.check_image(image).await,
+            Item::Table(table) => table.check_layout(),
+            Item::Text(text) => text.style.check(),
+            Item::StickyNote(note) => note.check_font_size_hint(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the details of a stored image, rejecting it if there is no such image
+    async fn check_image(&self, image: &mut ImageItem) -> Result<(), RejectReason> {
+        let info = media_info(&self.media_root, &image.url)
+            .await
+            .ok_or_else(|| RejectReason::NonExistentMedia {
+                url: image.url.clone(),
+            })?;
+
+        if let Some(crop) = &image.crop {
+            let (width, height) = (info.width as f64, info.height as f64);
+            let inside = crop.min.x >= 0.0
+                && crop.min.y >= 0.0
+                && crop.max.x <= width
+                && crop.max.y <= height;
+            if !inside || crop.width() <= 0.0 || crop.height() <= 0.0 {
+                return Err(RejectReason::IncorrectType {
+                    key: Some("crop".to_string()),
+                    expected: "Rect within the image",
+                    received: format!("{crop:?}"),
+                });
+            }
+        }
+
+        image.info = Some(info);
+        Ok(())
+    }
+
+    /// Update every connector attached to the moved items and notify clients of the new ends
+    ///
+    /// Connectors which are selected themselves are skipped, since they are being positioned by their selection
+    pub async fn update_connectors(&self, moved: &BTreeSet<ItemID>) {
+        if moved.is_empty() {
+            return;
+        }
+        for id in self.canvas.get_connectors(moved) {
+            let selected = self.selected_items.get_async(&id).await;
+            if selected.is_none_or(|entry| entry.get().is_some()) {
+                continue;
+            }
+            let Some(Item::Connector(mut connector)) = self.canvas.get_item(id).await else {
+                continue;
+            };
+            let (start, end) = (connector.start, connector.end);
+            if !self.attach_connector(&mut connector).await
+                || (start.x, start.y, end.x, end.y)
+                    == (
+                        connector.start.x,
+                        connector.start.y,
+                        connector.end.x,
+                        connector.end.y,
+                    )
+            {
+                continue;
+            }
+
+            let Some(mut item) = self.canvas.get_ref(id).await else {
+                continue;
+            };
+            // Only the ends are replaced, in case the connector was edited in the meantime
+            let Item::Connector(current) = &mut *item else {
+                continue;
+            };
+            current.start = connector.start;
+            current.end = connector.end;
+            let (edited, revision) = (item.clone(), item.revision());
+            // The item must be released before waiting on anything else
+            drop(item);
+            self.send_notify_c(SingleItemEdited {
+                id,
+                item: edited,
+                revision,
+            })
+            .await;
+        }
+    }
+
+    /// The frame an item was before it changed, to move its contents afterwards
+    pub fn frame_of(item: &ItemRef<'_>) -> Option<FrameItem> {
+        match &**item {
+            Item::Frame(frame) => Some(frame.clone()),
+            _ => None,
+        }
+    }
+
+    /// Move the items inside a frame along with it, returning the IDs of the items moved
+    ///
+    /// `old` is the frame before it changed and `new` is the item it changed into.
+    /// Items in `skip`, and items which are selected, are left where they are
+    pub async fn move_frame_contents(
+        &self,
+        old: &FrameItem,
+        new: &Item,
+        skip: &BTreeSet<ItemID>,
+    ) -> BTreeSet<ItemID> {
+        let Item::Frame(new) = new else {
+            return BTreeSet::new();
+        };
+        let Some(inverse) = old.transform.inverse() else {
+            return BTreeSet::new();
+        };
+        let components = |t: &Transform| {
+            let Transform {
+                origin,
+                basis_x,
+                basis_y,
+            } = t;
+            [
+                origin.x, origin.y, basis_x.x, basis_x.y, basis_y.x, basis_y.y,
+            ]
+        };
+        if components(&old.transform) == components(&new.transform) {
+            return BTreeSet::new();
+        }
+        let delta = new.transform.compose(&inverse);
+
+        let mut contents = Vec::new();
+        self.canvas
+            .scan_items(|id, item| {
+                if !skip.contains(&id) && old.contains(item) {
+                    contents.push(id);
+                }
+            })
+            .await;
+
+        let mut moved = BTreeSet::new();
+        for id in contents {
+            let selected = self.selected_items.get_async(&id).await;
+            // The entry must be released before waiting on the item
+            let owner = selected.map(|entry| *entry.get());
+            if owner.is_none_or(|owner| owner.is_some()) {
+                continue;
+            }
+            let Some(mut item) = self.canvas.get_ref(id).await else {
+                continue;
+            };
+            item.transform_by(&delta);
+            let (edited, revision) = (item.clone(), item.revision());
+            // The item must be released before waiting on anything else
+            drop(item);
+            self.send_notify_c(SingleItemEdited {
+                id,
+                item: edited,
+                revision,
+            })
+            .await;
+            moved.insert(id);
+        }
+        moved
+    }
+
     pub async fn send_notify_c(&self, msg: impl NotifyCType) {
-        let msg = msg.as_notify().as_msg();
-        let payload = MessagePayload::new(&msg);
+        let notify = msg.as_notify();
+        if in_batch() {
+            return buffer_notify(notify);
+        }
+        self.broadcast(&notify.as_msg()).await
+    }
+
+    /// Send a message to every connected client
+    pub async fn broadcast(&self, msg: &MsgSend) {
+        let payload = MessagePayload::new(msg);
         for id in self.client_ids.read().await.iter() {
             self.get_client(id).await.get().try_send_payload(&payload)
         }
@@ -0,0 +1,212 @@
+use std::{cell::RefCell, collections::BTreeMap};
+
+use log::error;
+use scc::hash_map::Entry;
+use serde_json::value::RawValue;
+
+use crate::{
+    client::{ClientHandle, CoalesceKey},
+    message::{
+        batch::{BatchCall, BatchResponse, NotifyCBatch, MAX_BATCH_LENGTH},
+        method::{CallOutcome, Methods},
+        notify_c::NotifyC,
+        reject::{RejectLevel, RejectMessage, RejectReason},
+        ClientID, MsgSend,
+    },
+};
+
+use super::{Board, SelectionState};
+
+/// The batch being handled by the current task
+struct BatchScope {
+    client: ClientID,
+    handle: ClientHandle,
+    notifications: RefCell<Vec<NotifyC>>,
+}
+
+tokio::task_local! {
+    static BATCH: BatchScope;
+}
+
+/// Whether the current task is handling a batch
+pub fn in_batch() -> bool {
+    BATCH.try_with(|_| ()).is_ok()
+}
+
+/// Get the response collector of the current batch if it belongs to the client
+pub fn batch_handle(id: &ClientID) -> Option<ClientHandle> {
+    BATCH
+        .try_with(|scope| (scope.client == *id).then(|| scope.handle.clone()))
+        .ok()
+        .flatten()
+}
+
+/// Hold a notification back until the current batch has finished
+///
+/// Panics if the task is not handling a batch, check with [`in_batch`] first
+pub fn buffer_notify(notify: NotifyC) {
+    BATCH.with(|scope| {
+        let mut notifications = scope.notifications.borrow_mut();
+        if let Some(key) = CoalesceKey::from_notify(&notify) {
+            notifications.retain(|n| CoalesceKey::from_notify(n) != Some(key));
+        }
+        notifications.push(notify);
+    })
+}
+
+/// Wrap a message stored by a collector so that it can be sent inside a [`BatchResponse`]
+fn raw_message(data: Vec<u8>) -> Result<Box<RawValue>, Box<dyn std::error::Error>> {
+    Ok(RawValue::from_string(String::from_utf8(data)?)?)
+}
+
+impl Board {
+    pub async fn handle_batch(&self, id: ClientID, batch: BatchCall) {
+        let client = self.get_handle(&id).await;
+
+        if let Some(reason) = Self::check_batch(&batch) {
+            if let Some(client) = client {
+                client.send_message(MsgSend::Reject(RejectMessage {
+                    request_protocol: "Batch",
+                    request_id: Some(batch.id),
+                    level: RejectLevel::Error,
+                    reason,
+                }));
+            }
+            return;
+        }
+
+        let collector = ClientHandle::new_collector();
+        let scope = BatchScope {
+            client: id,
+            handle: collector.clone(),
+            notifications: Default::default(),
+        };
+
+        let (applied, notifications) = if batch.atomic == Some(true) {
+            // Nothing else may touch the board while the batch could still be undone
+            let _guard = self.canvas.batch_lock().write().await;
+            // Other clients can't change anything, so only the batch's own changes are recorded
+            self.canvas.begin_undo();
+            let selection = self.get_client(&id).await.get().selection.clone();
+            let (outcome, notifications) = BATCH
+                .scope(scope, self.run_batch(id, batch.calls, true))
+                .await;
+            match outcome {
+                CallOutcome::Completed => {
+                    self.canvas.end_undo();
+                    (true, notifications)
+                }
+                CallOutcome::Failed => {
+                    self.undo_batch(id, selection).await;
+                    (false, Vec::new())
+                }
+            }
+        } else {
+            let _guard = self.canvas.batch_lock().read().await;
+            let (_, notifications) = BATCH
+                .scope(scope, self.run_batch(id, batch.calls, false))
+                .await;
+            (true, notifications)
+        };
+
+        if let Some(client) = client {
+            let messages = collector
+                .take_payloads()
+                .into_iter()
+                .filter_map(|data| {
+                    raw_message(data)
+                        .map_err(|e| error!("Failed to add a message to a batch response: {e}"))
+                        .ok()
+                })
+                .collect();
+
+            client.send_message(MsgSend::BatchResponse(BatchResponse {
+                id: batch.id,
+                applied,
+                messages,
+            }));
+        }
+
+        if !notifications.is_empty() {
+            self.broadcast(&MsgSend::NotifyCBatch(NotifyCBatch { notifications }))
+                .await;
+        }
+    }
+
+    /// Find any reason to refuse the whole batch before running it
+    fn check_batch(batch: &BatchCall) -> Option<RejectReason> {
+        if batch.calls.len() > MAX_BATCH_LENGTH {
+            return Some(RejectReason::MalformedMessage {
+                location: "calls".to_string(),
+            });
+        }
+
+        if batch.atomic == Some(true) {
+            // Paths stream to other clients as they are drawn, so can't be undone
+            let path_call = batch.calls.iter().position(|call| {
+                matches!(
+                    call,
+                    Methods::BeginPath(_) | Methods::ContinuePath(_) | Methods::EndPath(_)
+                )
+            });
+            if let Some(idx) = path_call {
+                return Some(RejectReason::MalformedMessage {
+                    location: format!("calls[{idx}]"),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Run each call in order, returning [`CallOutcome::Failed`] if any failed and the notifications they produced
+    ///
+    /// If `stop_on_failure` is set, no more calls are run after the first failure
+    async fn run_batch(
+        &self,
+        id: ClientID,
+        calls: Vec<Methods>,
+        stop_on_failure: bool,
+    ) -> (CallOutcome, Vec<NotifyC>) {
+        let mut outcome = CallOutcome::Completed;
+        for call in calls {
+            if self.handle_method(id, call).await == CallOutcome::Failed {
+                outcome = CallOutcome::Failed;
+                if stop_on_failure {
+                    break;
+                }
+            }
+        }
+        (outcome, BATCH.with(|scope| scope.notifications.take()))
+    }
+
+    /// Undo the changes an atomic batch made to the canvas and selections
+    ///
+    /// A batch can only take items nobody holds and release or delete its own, so only the
+    /// client's selection and the items the batch created need to be put back
+    async fn undo_batch(&self, id: ClientID, selection: SelectionState) {
+        let existed: BTreeMap<_, _> = self.canvas.undo().await.into_iter().collect();
+
+        let current = std::mem::replace(
+            &mut self.get_client(&id).await.get_mut().selection,
+            selection.clone(),
+        );
+
+        for (item_id, _) in existed.iter().filter(|(_, &existed)| !existed) {
+            self.selected_items.remove_async(item_id).await;
+        }
+
+        for &item_id in selection.items.keys().chain(current.items.keys()) {
+            if existed.get(&item_id) == Some(&false) {
+                continue;
+            }
+            let owner = selection.items.contains_key(&item_id).then_some(id);
+            match self.selected_items.entry_async(item_id).await {
+                Entry::Occupied(mut entry) => *entry.get_mut() = owner,
+                Entry::Vacant(entry) => {
+                    entry.insert_entry(owner);
+                }
+            }
+        }
+    }
+}
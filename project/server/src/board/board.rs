@@ -10,6 +10,7 @@ use crate::{
     message::{self, ClientID, ClientInfo, ConnectionInfo, MsgRecv},
 };
 use log::{error, warn};
+use std::time::Duration;
 use tokio::sync::oneshot;
 
 /// All of the types of events the board can receive
@@ -21,6 +22,7 @@ enum BoardMessage {
     ),
     ClientConnected(ClientID, ClientHandle),
     ClientDisconnected(ClientID),
+    ClientLatency(ClientID, Duration),
 }
 
 /// A reference to an active board that can be used to interact with it
@@ -63,6 +65,11 @@ impl BoardHandle {
         self.send_msg(BoardMessage::ClientDisconnected(id));
     }
 
+    /// Record the round-trip time of a client's most recent heartbeat
+    pub fn client_latency(&self, id: ClientID, latency: Duration) {
+        self.send_msg(BoardMessage::ClientLatency(id, latency));
+    }
+
     fn downgrade(&self) -> WeakHandle {
         WeakHandle(self.message_pipe.downgrade())
     }
@@ -1,17 +1,17 @@
 //! Implementation of boards stored on disk;
 
-use clap::builder::OsStr;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeSet,
-    fs::DirEntry,
-    io::{self, Seek, Write},
+    future::Future,
+    io::{self, Write},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    canvas::{ActiveCanvas, Item},
+    canvas::{comment::CommentThread, ActiveCanvas, Item},
+    message::{BoardID, ItemID},
     utils::IterExt,
 };
 
@@ -20,69 +20,172 @@ const fn _true() -> bool {
     true
 }
 
+/// How far (in board units) a simplified path may stray from the points that were drawn
+const DEFAULT_PATH_TOLERANCE: f64 = 0.025;
+
+/// Helper for `serde(default)`
+const fn default_path_tolerance() -> f64 {
+    DEFAULT_PATH_TOLERANCE
+}
+
+/// Whether a path tolerance can be passed to [`crate::canvas::Spline::simplify`]
+fn valid_path_tolerance(tolerance: f64) -> bool {
+    tolerance.is_finite() && tolerance >= 0.0
+}
+
+/// Stored next to the board file, so that it can be read without parsing every item
+///
+/// Older board files have these fields in the board file itself, they are moved out when the board is first found
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct BoardFileAttrs {
+    /// Kept in the file so that it doesn't change when the file is renamed
+    #[serde(default = "BoardID::new")]
+    pub id: BoardID,
     #[serde(default = "_true")]
     pub readonly: bool,
+    /// See [`crate::canvas::Spline::simplify`], zero keeps paths as they were drawn
+    ///
+    /// Set with [`BoardFileHandle::set_path_tolerance`], and only used by the board the next time it is loaded
+    #[serde(default = "default_path_tolerance")]
+    pub path_tolerance: f64,
+    /// The edit count of the canvas when it was last saved, which it continues from when loaded
+    #[serde(default)]
+    pub edit_count: u64,
+    /// The edit count of the canvas when the stored thumbnail was rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_edits: Option<u64>,
+}
+
+impl Default for BoardFileAttrs {
+    fn default() -> Self {
+        Self {
+            id: BoardID::new(),
+            readonly: true,
+            path_tolerance: DEFAULT_PATH_TOLERANCE,
+            edit_count: 0,
+            thumbnail_edits: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct BoardFile {
     pub items: Vec<Item>,
-    #[serde(flatten)]
-    pub attrs: BoardFileAttrs,
+    /// The ID of each item in `items`, so that references between items survive reloading
+    #[serde(default)]
+    pub item_ids: Vec<ItemID>,
+    #[serde(default)]
+    pub threads: Vec<CommentThread>,
 }
 
 pub struct BoardFileHandle {
     file_path: PathBuf,
     temp_path: PathBuf,
+    thumbnail_path: PathBuf,
+    attrs_path: PathBuf,
     attrs: BoardFileAttrs,
 }
 
 impl BoardFileHandle {
-    pub fn from_path(file_path: PathBuf) -> Self {
-        Self {
+    pub async fn from_path(file_path: PathBuf) -> io::Result<Self> {
+        let attrs_path = file_path.with_extension("attrs");
+        let mut attrs = Self::read_attrs(&attrs_path, &file_path).await?;
+        if !valid_path_tolerance(attrs.path_tolerance) {
+            warn!(
+                "Ignoring path tolerance {} in {}",
+                attrs.path_tolerance,
+                attrs_path.display()
+            );
+            attrs.path_tolerance = DEFAULT_PATH_TOLERANCE;
+        }
+        Ok(Self {
             temp_path: file_path.with_extension("json.swp"),
+            thumbnail_path: file_path.with_extension("thumb.png"),
+            attrs_path,
             file_path,
-            attrs: BoardFileAttrs { readonly: true },
+            attrs,
+        })
+    }
+
+    /// Read the attributes of a board, moving them out of the board file if it predates attribute files
+    /// or creating them if the board is new
+    ///
+    /// An attributes file that can't be read is left alone and the board refused, since replacing it
+    /// would give the board a new ID and break every link to it
+    async fn read_attrs(attrs_path: &Path, file_path: &Path) -> io::Result<BoardFileAttrs> {
+        match tokio::fs::read(attrs_path).await {
+            Ok(data) => return Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            Err(_) => {}
         }
+
+        let attrs = match tokio::fs::read(file_path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            // The board hasn't been saved yet
+            Err(_) => BoardFileAttrs::default(),
+        };
+        // Written straight away so that links made to a new board still lead to it after a restart
+        let written = match serde_json::to_vec(&attrs) {
+            Ok(data) => tokio::fs::write(attrs_path, data).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            warn!("Failed to write {}: {e}", attrs_path.display());
+        }
+        Ok(attrs)
+    }
+
+    fn write_attrs(attrs_path: &Path, attrs: &BoardFileAttrs) -> io::Result<()> {
+        std::fs::write(attrs_path, serde_json::to_vec(attrs)?)
     }
 
     /// Create a handle for a board which may not exist on the filesystem
-    pub fn create_new(root: &Path, name: &str) -> Self {
+    pub async fn create_new(root: &Path, name: &str) -> io::Result<Self> {
         let file_name = filenamify::filenamify(name).replace('.', "_");
         let mut file_path = root.join(file_name);
         file_path.set_extension("json");
-        Self::from_path(file_path)
+        Self::from_path(file_path).await
     }
 
-    fn try_get_board(entry: DirEntry) -> Option<(String, Self)> {
-        let path = entry.path();
+    fn board_name(path: &Path) -> Option<String> {
         let file_name = path.file_name()?.to_str()?;
-        if let Some(name) = file_name.strip_suffix(".json") {
-            Some((name.to_string(), BoardFileHandle::from_path(path)))
-        } else {
-            None
-        }
+        Some(file_name.strip_suffix(".json")?.to_string())
     }
 
-    pub fn get_boards(path: &Path) -> io::Result<Vec<(String, Self)>> {
+    pub async fn get_boards(path: &Path) -> io::Result<Vec<(String, Self)>> {
         use std::fs;
 
         let mut handles = Vec::new();
 
         let dirs = fs::read_dir(path)?;
-        for entry in dirs.filter_ok() {
-            if let Some(board) = Self::try_get_board(entry) {
-                handles.push(board)
+        let paths: Vec<_> = dirs.filter_ok().map(|entry| entry.path()).collect();
+        for path in paths {
+            let Some(name) = Self::board_name(&path) else {
+                continue;
+            };
+            match Self::from_path(path).await {
+                Ok(handle) => handles.push((name, handle)),
+                Err(e) => warn!("Not opening board {name}: {e}"),
             }
         }
 
         Ok(handles)
     }
 
-    pub async fn load_canvas(&mut self) -> io::Result<ActiveCanvas> {
-        let data = tokio::fs::read(&self.file_path).await?;
+    pub async fn load_canvas(&self) -> io::Result<ActiveCanvas> {
+        self.canvas_loader().await
+    }
+
+    /// Read the canvas from disk without borrowing the handle, so that it can be released while the file loads
+    pub fn canvas_loader(&self) -> impl Future<Output = io::Result<ActiveCanvas>> + 'static {
+        let (file_path, edit_count) = (self.file_path.clone(), self.attrs.edit_count);
+        async move { Self::read_canvas(&file_path, edit_count).await }
+    }
+
+    async fn read_canvas(file_path: &Path, edit_count: u64) -> io::Result<ActiveCanvas> {
+        let data = tokio::fs::read(file_path).await?;
 
         let parsed = serde_json::from_slice::<BoardFile>(&data).map_err(|e| {
             debug!("Error parsing board file: {e}");
@@ -91,11 +194,28 @@ impl BoardFileHandle {
 
         let mut canvas = ActiveCanvas::new_empty();
 
-        for item in parsed.items {
-            canvas.add_item_owned(item);
+        let mut items = parsed.items;
+        // Colors weren't always checked, so one bad color shouldn't stop the rest of the board loading
+        for item in &mut items {
+            item.repair_colors();
+        }
+
+        // Older files don't record IDs, in which case they are handed out in order
+        if parsed.item_ids.len() == items.len() {
+            for (id, item) in parsed.item_ids.into_iter().zip(items) {
+                canvas.add_item_owned_with_id(id, item);
+            }
+        } else {
+            for item in items {
+                canvas.add_item_owned(item);
+            }
+        }
+
+        for thread in parsed.threads {
+            canvas.add_thread_owned(thread);
         }
 
-        self.attrs = parsed.attrs;
+        canvas.set_edit_count(edit_count);
 
         Ok(canvas)
     }
@@ -103,34 +223,85 @@ impl BoardFileHandle {
     pub async fn save_canvas(&mut self, canvas: &ActiveCanvas) -> io::Result<()> {
         let mut seen_ids = BTreeSet::new();
 
+        // Taken first so that edits made while saving are saved next time
+        let edit_count = canvas.edit_count();
+
         let mut file = std::fs::File::create(&self.temp_path)?;
 
-        serde_json::to_writer(&file, &self.attrs)?;
-        // Clear the closing brace and start the item list
-        file.seek(io::SeekFrom::Current(-1))?;
-        file.write(br#","items":["#)?;
+        file.write_all(br#"{"items":["#)?;
+
+        let mut item_ids = Vec::new();
+        // The first failure stops the save, so that a broken file never replaces the last good one
+        let mut written = Ok(());
 
         canvas
             .scan_items(|id, item| {
-                if !seen_ids.contains(&id) {
+                if written.is_ok() && !seen_ids.contains(&id) {
                     trace!("Serialising item {id:?} during autosave");
                     seen_ids.insert(id);
-                    serde_json::to_writer(&file, item);
-                    file.write(b",");
+                    let separator = if item_ids.is_empty() { &b""[..] } else { b"," };
+                    written = file
+                        .write_all(separator)
+                        .and_then(|_| Ok(serde_json::to_writer(&file, item)?));
+                    item_ids.push(id);
                 }
             })
             .await;
+        written?;
 
-        file.seek(io::SeekFrom::Current(-1)).unwrap();
-
-        file.write(b"]}").unwrap();
+        file.write_all(br#"],"itemIds":"#)?;
+        serde_json::to_writer(&file, &item_ids)?;
+        file.write_all(br#","threads":"#)?;
+        serde_json::to_writer(&file, &canvas.get_threads().await)?;
+        file.write_all(b"}")?;
 
-        file.sync_all().unwrap();
+        file.sync_all()?;
 
         drop(file);
 
-        std::fs::rename(&self.temp_path, &self.file_path).expect("Failed to overwrite board file");
+        std::fs::rename(&self.temp_path, &self.file_path)?;
+
+        self.attrs.edit_count = edit_count;
+        Self::write_attrs(&self.attrs_path, &self.attrs)
+    }
+
+    /// See [`BoardID`]
+    pub fn id(&self) -> BoardID {
+        self.attrs.id
+    }
+
+    /// The tolerance used to simplify paths drawn on this board
+    pub fn path_tolerance(&self) -> f64 {
+        self.attrs.path_tolerance
+    }
+
+    /// Change the tolerance used to simplify paths, which must be finite and not negative
+    pub fn set_path_tolerance(&mut self, tolerance: f64) -> io::Result<()> {
+        if !valid_path_tolerance(tolerance) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Path tolerance must be a finite number of at least 0, not {tolerance}"),
+            ));
+        }
+        self.attrs.path_tolerance = tolerance;
+        Self::write_attrs(&self.attrs_path, &self.attrs)
+    }
+
+    /// The edit count of the canvas when the stored thumbnail was rendered, if there is one
+    pub fn thumbnail_edits(&self) -> Option<u64> {
+        self.attrs.thumbnail_edits
+    }
+
+    /// Read the stored thumbnail without borrowing the handle, see [`Self::canvas_loader`]
+    pub fn thumbnail_loader(&self) -> impl Future<Output = io::Result<Vec<u8>>> + 'static {
+        let thumbnail_path = self.thumbnail_path.clone();
+        async move { tokio::fs::read(&thumbnail_path).await }
+    }
 
-        Ok(())
+    /// Store a thumbnail rendered when the canvas was at `edit_count` edits
+    pub async fn save_thumbnail(&mut self, png: &[u8], edit_count: u64) -> io::Result<()> {
+        tokio::fs::write(&self.thumbnail_path, png).await?;
+        self.attrs.thumbnail_edits = Some(edit_count);
+        Self::write_attrs(&self.attrs_path, &self.attrs)
     }
 }
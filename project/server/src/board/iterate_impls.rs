@@ -18,8 +18,8 @@ impl Board {
         let (params, mut handle) = call.get_handle(self.get_client(&id).await.get().handle.clone());
 
         for (idx, id) in params.ids.into_iter().enumerate() {
-            if let Some(item) = self.canvas.get_item(id).await {
-                handle.add_item(message::Ok((id, item)));
+            if let Some((item, revision)) = self.canvas.get_item_revision(id).await {
+                handle.add_item(message::Ok((id, item, revision)));
             } else {
                 handle.add_item(message::Err(message::ErrorCode::NotFound.into()));
             }
@@ -1,27 +1,58 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use log::{debug, trace};
-use scc::HashMap as AsyncHashMap;
+use log::{debug, error, trace, warn};
+use scc::{hash_map::Entry, HashMap as AsyncHashMap};
 
-use crate::canvas::ActiveCanvas;
+use crate::{
+    canvas::{
+        item::{BoardLink, LinkDestination},
+        ActiveCanvas,
+    },
+    export::png::render_thumbnail,
+    message::{
+        self as m,
+        reject::{helpers::non_existent_id, RejectReason},
+        BoardID,
+    },
+};
 
 use super::{active::from_canvas, file::BoardFileHandle, BoardHandle, WeakHandle};
 
 static BOARD_TASKS: usize = 4;
 
 struct LoadedState {
-    handle: WeakHandle,
+    /// The running board, if it has been started
+    handle: Option<WeakHandle>,
     canvas: Arc<ActiveCanvas>,
 }
 
 impl LoadedState {
+    fn new(canvas: Arc<ActiveCanvas>, handle: Option<WeakHandle>) -> Self {
+        Self { handle, canvas }
+    }
+
     /// Either create a new active board or return the current one
-    fn get_or_refresh(&mut self) -> BoardHandle {
-        if let Some(handle) = self.handle.upgrade() {
+    fn get_or_refresh(
+        &mut self,
+        boards: &'static BoardManager,
+        file: &BoardFileHandle,
+    ) -> BoardHandle {
+        if let Some(handle) = self.handle.as_ref().and_then(WeakHandle::upgrade) {
             handle
         } else {
-            let handle = from_canvas(self.canvas.clone(), BOARD_TASKS);
-            self.handle = handle.downgrade();
+            let handle = from_canvas(
+                self.canvas.clone(),
+                BOARD_TASKS,
+                boards.media_root.clone(),
+                boards,
+                file.path_tolerance(),
+            );
+            self.handle = Some(handle.downgrade());
             handle
         }
     }
@@ -38,18 +69,49 @@ struct BoardRef {
     state: ActiveState,
 }
 
+impl BoardRef {
+    /// Get the canvas without keeping it in memory if it isn't already
+    ///
+    /// The returned future doesn't borrow the board, so it should be released before waiting on it
+    fn peek_canvas(&self) -> impl Future<Output = Option<Arc<ActiveCanvas>>> + 'static {
+        let loaded = match &self.state {
+            ActiveState::Loaded(state) => Some(state.canvas.clone()),
+            ActiveState::Unloaded => None,
+        };
+        let loader = self.file.canvas_loader();
+        async move {
+            match loaded {
+                Some(canvas) => Some(canvas),
+                None => Some(Arc::new(loader.await.ok()?)),
+            }
+        }
+    }
+
+    /// The canvas and its edit count if it has changed since the stored thumbnail was rendered
+    fn outdated_thumbnail(&self) -> Option<(Arc<ActiveCanvas>, u64)> {
+        let ActiveState::Loaded(state) = &self.state else {
+            return None;
+        };
+        let edits = state.canvas.edit_count();
+        (self.file.thumbnail_edits() != Some(edits)).then(|| (state.canvas.clone(), edits))
+    }
+}
+
 /// Maintains a table of boards and fetches handles as requested
 pub struct BoardManager {
     path: &'static Path,
+    media_root: PathBuf,
     boards: AsyncHashMap<String, BoardRef>,
 }
 
 impl BoardManager {
     /// Create a new manager
-    pub fn new(path: &'static Path) -> Self {
+    ///
+    /// `media_root` is used to load images when rendering thumbnails
+    pub async fn new(path: &'static Path, media_root: PathBuf) -> Self {
         let boards = AsyncHashMap::new();
 
-        for (name, handle) in BoardFileHandle::get_boards(path).unwrap() {
+        for (name, handle) in BoardFileHandle::get_boards(path).await.unwrap() {
             let _ = boards.insert(
                 name,
                 BoardRef {
@@ -58,65 +120,210 @@ impl BoardManager {
                 },
             );
         }
-        Self { boards, path }
+        Self {
+            boards,
+            path,
+            media_root,
+        }
     }
 
     /// Starts the requested board (if available) and returns a handle
-    pub async fn load_board(&self, board_name: String) -> BoardHandle {
-        let mut entry = self
-            .boards
-            .entry_async(board_name.clone())
-            .await
-            .or_insert_with(|| BoardRef {
-                file: BoardFileHandle::create_new(self.path, &board_name),
-                state: ActiveState::Unloaded,
-            });
+    ///
+    /// A board whose files can't be read is not opened, so that they aren't saved over
+    pub async fn load_board(&'static self, board_name: String) -> Result<BoardHandle, m::Error> {
+        let load_failed = |e: io::Error| {
+            error!("Failed to load board {board_name}: {e}");
+            m::Error {
+                code: m::ErrorCode::Internal,
+                msg: Some("The board could not be loaded".to_string()),
+            }
+        };
+
+        let mut entry = match self.boards.entry_async(board_name.clone()).await {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => {
+                let file = BoardFileHandle::create_new(self.path, &board_name)
+                    .await
+                    .map_err(load_failed)?;
+                entry.insert_entry(BoardRef {
+                    file,
+                    state: ActiveState::Unloaded,
+                })
+            }
+        };
 
         let board = entry.get_mut();
         match &mut board.state {
-            ActiveState::Loaded(state) => state.get_or_refresh(),
+            ActiveState::Loaded(state) => Ok(state.get_or_refresh(self, &board.file)),
             ActiveState::Unloaded => {
                 debug!("Trying to load a new board");
-                let canvas = board
-                    .file
-                    .load_canvas()
-                    .await
-                    .unwrap_or_else(|_| ActiveCanvas::new_empty());
+                let canvas = match board.file.load_canvas().await {
+                    Ok(canvas) => canvas,
+                    // The board hasn't been saved yet
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => ActiveCanvas::new_empty(),
+                    Err(e) => return Err(load_failed(e)),
+                };
                 let canvas = Arc::new(canvas);
 
-                let handle = from_canvas(canvas.clone(), BOARD_TASKS);
+                let handle = from_canvas(
+                    canvas.clone(),
+                    BOARD_TASKS,
+                    self.media_root.clone(),
+                    self,
+                    board.file.path_tolerance(),
+                );
 
-                let state = LoadedState {
-                    handle: handle.downgrade(),
-                    canvas,
-                };
+                let state = LoadedState::new(canvas, Some(handle.downgrade()));
                 board.state = ActiveState::Loaded(state);
 
-                handle
+                Ok(handle)
             }
         }
     }
 
-    /// Flush all boards to disk
-    pub async fn autosave(&self) {
-        async {
-            let mut current_entry = self.boards.first_entry_async().await?;
-
-            loop {
-                let board = current_entry.get_mut();
+    /// Get the canvas of an existing board without starting it
+    ///
+    /// A board which isn't loaded is read from disk for the caller only, and not kept in memory
+    pub async fn get_canvas(&self, board_name: &str) -> Option<Arc<ActiveCanvas>> {
+        let peek = self.boards.get_async(board_name).await?.get().peek_canvas();
+        peek.await
+    }
 
-                if let ActiveState::Loaded(state) = &board.state {
-                    let _ = board.file.save_canvas(&state.canvas).await;
+    /// Change how closely paths drawn on a board are kept to the points they were drawn through
+    ///
+    /// A board which is already loaded keeps using its previous tolerance until it is loaded again
+    pub async fn set_path_tolerance(&self, board_name: &str, tolerance: f64) -> io::Result<()> {
+        let mut entry = self.boards.get_async(board_name).await.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No board named {board_name}"),
+            )
+        })?;
+        entry.get_mut().file.set_path_tolerance(tolerance)
+    }
 
-                    trace!("Autosaved board {}", current_entry.key());
+    /// Find the name a board is currently stored under
+    pub async fn board_name(&self, id: BoardID) -> Option<String> {
+        let mut name = None;
+        self.boards
+            .scan_async(|board_name, board| {
+                if board.file.id() == id {
+                    name = Some(board_name.clone());
                 }
-                current_entry = current_entry.next_async().await?;
+            })
+            .await;
+        name
+    }
+
+    /// Check that a link leads somewhere that exists, bringing its board name and ID up to date
+    pub async fn resolve_link(&self, link: &mut BoardLink) -> Result<(), RejectReason> {
+        // The ID is more reliable than the name, which may have changed since the link was made.
+        // An unknown ID falls back to the name, in case the link came from another server
+        if let Some(id) = link.board_id {
+            if let Some(name) = self.board_name(id).await {
+                link.board = name;
+            }
+        }
+
+        let entry = self.boards.get_async(&link.board).await.ok_or_else(|| {
+            RejectReason::NonExistentBoard {
+                name: link.board.clone(),
             }
+        })?;
+        let board = entry.get();
+        link.board_id = Some(board.file.id());
+        let peek = board.peek_canvas();
+        drop(entry);
+
+        if let Some(LinkDestination::Item(item_id)) = link.destination {
+            let exists = match peek.await {
+                Some(canvas) => canvas.get_item(item_id).await.is_some(),
+                None => false,
+            };
+            if !exists {
+                return Err(non_existent_id(item_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the stored thumbnail of a board as a PNG, rendering it if there is none yet
+    pub async fn get_thumbnail(&self, board_name: &str) -> Option<Vec<u8>> {
+        let (thumbnail, peek) = {
+            let entry = self.boards.get_async(board_name).await?;
+            let board = entry.get();
+            (board.file.thumbnail_loader(), board.peek_canvas())
+        };
+        if let Ok(png) = thumbnail.await {
+            return Some(png);
+        }
+        let canvas = peek.await?;
+        let edits = canvas.edit_count();
+        let png = render_thumbnail(&canvas, self.media_root.clone())
+            .await
+            .map_err(|e| warn!("Failed to render thumbnail: {e}"))
+            .ok()?;
+        self.store_thumbnail(board_name, &png, edits).await;
+        Some(png)
+    }
 
-            // needed for type inference
-            #[allow(unreachable_code)]
-            None::<()>
+    /// Render and store a new thumbnail if the canvas has changed since the last one
+    async fn update_thumbnail(&self, board_name: &str) {
+        let outdated = match self.boards.get_async(board_name).await {
+            Some(entry) => entry.get().outdated_thumbnail(),
+            None => None,
+        };
+        let Some((canvas, edits)) = outdated else {
+            return;
+        };
+        // The board isn't locked while rendering, which can take a while
+        match render_thumbnail(&canvas, self.media_root.clone()).await {
+            Ok(png) => self.store_thumbnail(board_name, &png, edits).await,
+            Err(e) => warn!("Failed to render thumbnail: {e}"),
+        }
+    }
+
+    async fn store_thumbnail(&self, board_name: &str, png: &[u8], edits: u64) {
+        let Some(mut entry) = self.boards.get_async(board_name).await else {
+            return;
+        };
+        if let Err(e) = entry.get_mut().file.save_thumbnail(png, edits).await {
+            warn!("Failed to save thumbnail: {e}");
+        }
+    }
+
+    /// Flush all boards to disk
+    pub async fn autosave(&self) {
+        let mut loaded = Vec::new();
+        self.boards
+            .scan_async(|name, board| {
+                if let ActiveState::Loaded(_) = board.state {
+                    loaded.push(name.clone());
+                }
+            })
+            .await;
+
+        for name in loaded {
+            let canvas = match self.boards.get_async(&name).await {
+                Some(entry) => match &entry.get().state {
+                    ActiveState::Loaded(state) => state.canvas.clone(),
+                    ActiveState::Unloaded => continue,
+                },
+                None => continue,
+            };
+            // An atomic batch that is still running may be undone, so it shouldn't be saved half done.
+            // The board is released while waiting, since the batch may need to look up other boards
+            let batch_lock = canvas.batch_lock().read().await;
+            if let Some(mut entry) = self.boards.get_async(&name).await {
+                if let Err(e) = entry.get_mut().file.save_canvas(&canvas).await {
+                    warn!("Failed to autosave board {name}: {e}");
+                    continue;
+                }
+                trace!("Autosaved board {name}");
+            }
+            drop(batch_lock);
+            self.update_thumbnail(&name).await;
         }
-        .await;
     }
 }
@@ -1,25 +1,39 @@
+use std::collections::BTreeSet;
+
 use log::debug;
 use scc::hash_map::Entry;
 use tokio::time::Instant;
 
 use crate::{
-    canvas::{item::PathItem, Spline, Transform},
+    canvas::{
+        active::ItemRef,
+        comment::{Comment, CommentAnchor, MAX_COMMENT_LENGTH},
+        item::{PathItem, TableItem},
+        recognize::recognize_shape,
+        Item, Marker, Spline, Transform,
+    },
+    import::svg::{discard_import, import_svg},
     message::{
         self as m,
         method::*,
         notify_c::{
-            ItemCreated, ItemsDeleted, PathStarted, SelectionItemsAdded, SelectionItemsRemoved,
-            SelectionMoved, SingleItemEdited,
+            CommentAdded, ItemCreated, ItemsDeleted, PathStarted, SelectionItemsAdded,
+            SelectionItemsRemoved, SelectionMoved, SingleItemEdited, SingleItemPatched,
+            TableCellEdited, TableColumnDeleted, TableColumnInserted, TableRowDeleted,
+            TableRowInserted, ThreadCreated, ThreadMoved, ThreadReopened, ThreadResolved,
+        },
+        reject::{
+            helpers::{non_existent_id, resource_not_owned},
+            RejectReason,
         },
-        reject::helpers::{non_existent_id, resource_not_owned},
-        ClientID, ErrorCode, PathID,
+        ClientID, EndedPath, ErrorCode, FrameInfo, ItemID, PathID, ThreadID,
     },
 };
 
 use super::{ActivePath, Board};
 
 impl Board {
-    pub async fn handle_method(&self, id: ClientID, method: Methods) {
+    pub async fn handle_method(&self, id: ClientID, method: Methods) -> CallOutcome {
         match method {
             Methods::SelectionAddItems(call) => self.handle_selection_add_items(id, call).await,
             Methods::SelectionRemoveItems(call) => {
@@ -27,17 +41,100 @@ impl Board {
             }
             Methods::SelectionMove(call) => self.handle_selection_move(id, call).await,
             Methods::EditSingleItem(call) => self.handle_edit_single_item(id, call).await,
+            Methods::PatchSingleItem(call) => self.handle_patch_single_item(id, call).await,
             Methods::DeleteItems(call) => self.handle_delete_items(id, call).await,
             Methods::CreateItem(call) => self.handle_create_item(id, call).await,
+            Methods::ImportSvg(call) => self.handle_import_svg(id, call).await,
+            Methods::TableInsertRow(call) => self.handle_table_insert_row(id, call).await,
+            Methods::TableDeleteRow(call) => self.handle_table_delete_row(id, call).await,
+            Methods::TableInsertColumn(call) => self.handle_table_insert_column(id, call).await,
+            Methods::TableDeleteColumn(call) => self.handle_table_delete_column(id, call).await,
+            Methods::TableEditCell(call) => self.handle_table_edit_cell(id, call).await,
+            Methods::CreateThread(call) => self.handle_create_thread(id, call).await,
+            Methods::ReplyToThread(call) => self.handle_reply_to_thread(id, call).await,
+            Methods::ResolveThread(call) => self.handle_resolve_thread(id, call).await,
+            Methods::ReopenThread(call) => self.handle_reopen_thread(id, call).await,
             Methods::BeginPath(call) => self.handle_begin_path(id, call).await,
             Methods::ContinuePath(call) => self.handle_continue_path(id, call).await,
             Methods::EndPath(call) => self.handle_end_path(id, call).await,
             Methods::GetAllItemIDs(call) => self.handle_get_all_item_ids(id, call).await,
+            Methods::GetItemsAtPoint(call) => self.handle_get_items_at_point(id, call).await,
+            Methods::GetFrames(call) => self.handle_get_frames(id, call).await,
+            Methods::GetAllThreads(call) => self.handle_get_all_threads(id, call).await,
+            Methods::FollowLink(call) => self.handle_follow_link(id, call).await,
             Methods::GetAllClientIDs(call) => self.handle_get_all_client_ids(id, call).await,
             Methods::GetClientState(call) => self.handle_get_client_state(id, call).await,
         }
     }
 
+    /// Produce a [`ErrorCode::Conflict`] error if the item is not at the expected revision
+    fn check_revision(item: &ItemRef<'_>, expected: Option<u32>) -> Option<m::Error> {
+        let current = item.revision();
+        match expected {
+            Some(expected) if expected != current => Some(m::Error {
+                code: ErrorCode::Conflict,
+                msg: Some(format!(
+                    "Item is at revision {current}, expected {expected}"
+                )),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Apply an edit to a table, replying to the method with the outcome.
+    /// Returns the table's new revision if the edit succeeded
+    ///
+    /// The table doesn't need to be selected, but is refused if another client has selected it
+    async fn edit_table<T: MethodType<Response = m::Result>>(
+        &self,
+        id: ClientID,
+        handle: MethodHandle<T>,
+        item_id: ItemID,
+        expected_revision: Option<u32>,
+        edit: impl FnOnce(&mut TableItem) -> Result<(), String>,
+    ) -> Option<u32> {
+        let selected = self.selected_items.get_async(&item_id).await;
+        if selected.is_some_and(|entry| entry.get().is_some_and(|owner| owner != id)) {
+            handle.error(resource_not_owned(item_id));
+            return None;
+        }
+
+        let Some(mut item) = self.canvas.get_ref(item_id).await else {
+            handle.error(non_existent_id(item_id));
+            return None;
+        };
+
+        let Item::Table(table) = &*item else {
+            handle.error(RejectReason::IncorrectType {
+                key: Some(item_id.to_string()),
+                expected: "Table",
+                received: item.type_name().to_string(),
+            });
+            return None;
+        };
+
+        if let Some(err) = Self::check_revision(&item, expected_revision) {
+            handle.err(err);
+            return None;
+        }
+
+        // Edit a copy so that the revision is only bumped once the edit is known to succeed
+        let mut table = table.clone();
+        if let Err(msg) = edit(&mut table) {
+            handle.err(m::Error {
+                code: ErrorCode::BadData,
+                msg: Some(msg),
+            });
+            return None;
+        }
+        *item = Item::Table(table);
+        let revision = item.revision();
+        drop(item);
+
+        handle.ok(());
+        Some(revision)
+    }
+
     async fn make_handle<T: MethodType>(
         &self,
         id: ClientID,
@@ -46,7 +143,11 @@ impl Board {
         call.create_handle(self.get_handle(&id).await)
     }
 
-    async fn handle_selection_add_items(&self, id: ClientID, call: Call<SelectionAddItems>) {
+    async fn handle_selection_add_items(
+        &self,
+        id: ClientID,
+        call: Call<SelectionAddItems>,
+    ) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let mut old_sits_checked = Vec::with_capacity(params.old_sits.len());
@@ -96,21 +197,23 @@ impl Board {
 
         drop(client);
 
-        handle.respond(return_results);
+        let outcome = handle.respond(return_results);
 
         self.send_notify_c(SelectionItemsAdded {
             id,
             items: new_ids,
             new_srt: params.new_srt,
         })
-        .await
+        .await;
+
+        outcome
     }
 
     async fn handle_selection_remove_items(
         &self,
         client_id: ClientID,
         call: Call<SelectionRemoveItems>,
-    ) {
+    ) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&client_id).await);
 
         let mut client = self.get_client(&client_id).await;
@@ -119,6 +222,8 @@ impl Board {
 
         let mut ok = true;
 
+        let mut frames = Vec::new();
+
         for (item_id, update) in params.items {
             let entry = self.selected_items.entry_async(item_id).await;
             let Entry::Occupied(mut entry) = entry else {
@@ -129,40 +234,63 @@ impl Board {
             if *entry.get() == Some(client_id) {
                 let item = self.canvas.get_ref(item_id).await;
                 let Some(mut item) = item else { continue };
-                let res = item.apply_location_update(item_id, &update);
-                *entry.get_mut() = None;
-                if let Err((update, reason)) = res {
-                    out.push((item_id, update));
-                    handle.warn(reason);
-                } else {
-                    out.push((item_id, update));
+                let frame = Self::frame_of(&item);
+                // Moved on a copy so that a refused update doesn't count as a change
+                let mut updated = item.clone();
+                let res = updated.apply_location_update(item_id, &update);
+                if res.is_ok() {
+                    *item = updated;
+                }
+                if let Some(frame) = frame {
+                    frames.push((frame, item.clone()));
                 }
+                *entry.get_mut() = None;
+                let update = match res {
+                    Ok(()) => update,
+                    Err((original, reason)) => {
+                        handle.warn(reason);
+                        original
+                    }
+                };
+                out.push((item_id, update, item.revision()));
             } else {
                 handle.warn(resource_not_owned(item_id));
                 ok = false;
             }
         }
 
-        for (id, _) in out.iter() {
+        for (id, _, _) in out.iter() {
             client.get_mut().selection.items.remove(id);
         }
 
         drop(client);
 
-        if ok {
-            handle.ok(());
+        let outcome = if ok {
+            handle.ok(())
         } else {
             handle.err(ErrorCode::BadData.into())
-        }
+        };
+
+        let mut moved: BTreeSet<_> = out.iter().map(|&(id, _, _)| id).collect();
 
         self.send_notify_c(SelectionItemsRemoved {
             id: client_id,
             items: out,
         })
         .await;
+
+        // The rest of the selection has already been placed, so only the other items follow frames
+        let placed = moved.clone();
+        for (frame, item) in frames {
+            moved.extend(self.move_frame_contents(&frame, &item, &placed).await);
+        }
+
+        self.update_connectors(&moved).await;
+
+        outcome
     }
 
-    async fn handle_selection_move(&self, id: ClientID, call: Call<SelectionMove>) {
+    async fn handle_selection_move(&self, id: ClientID, call: Call<SelectionMove>) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let new_sits;
@@ -181,7 +309,7 @@ impl Board {
             new_sits = None;
         }
 
-        {
+        let moved: BTreeSet<_> = {
             let mut client = self.get_client(&id).await;
             let selection = &mut client.get_mut().selection;
 
@@ -190,7 +318,9 @@ impl Board {
             for (item_id, transform) in new_sits.iter().flatten() {
                 selection.items.insert(*item_id, transform.clone());
             }
-        }
+
+            selection.items.keys().copied().collect()
+        };
 
         self.send_notify_c(SelectionMoved {
             id,
@@ -199,11 +329,19 @@ impl Board {
         })
         .await;
 
-        handle.respond(());
+        let outcome = handle.respond(());
+
+        self.update_connectors(&moved).await;
+
+        outcome
     }
 
-    async fn handle_edit_single_item(&self, id: ClientID, call: Call<EditSingleItem>) {
-        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+    async fn handle_edit_single_item(
+        &self,
+        id: ClientID,
+        call: Call<EditSingleItem>,
+    ) -> CallOutcome {
+        let (mut params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let selected = self.selected_items.get_async(&params.item_id).await;
 
@@ -217,24 +355,136 @@ impl Board {
 
         drop(selected);
 
+        if let Err(reason) = self.validate_item(&mut params.item).await {
+            return handle.error(reason);
+        }
+
         debug!("Editing item {:?}", params.item_id);
 
-        let mut item = self.canvas.get_ref(params.item_id).await.unwrap(); // Checked earlier that item exists
+        let name = self.get_client(&id).await.get().info.name.clone();
+        // The item may have been deleted while it was validated
+        let Some(mut item) = self.canvas.get_ref(params.item_id).await else {
+            return handle.error(non_existent_id(params.item_id));
+        };
+
+        if let Some(err) = Self::check_revision(&item, params.expected_revision) {
+            return handle.err(err);
+        }
+
+        let frame = Self::frame_of(&item);
+        Self::keep_author(&item, &mut params.item, &name);
         *item = params.item.clone();
+        let revision = item.revision();
+        drop(item);
 
-        handle.ok(());
+        let outcome = handle.ok(());
 
         self.send_notify_c(SingleItemEdited {
             id: params.item_id,
-            item: params.item,
+            item: params.item.clone(),
+            revision,
         })
         .await;
+
+        let mut moved = BTreeSet::from([params.item_id]);
+        if let Some(frame) = frame {
+            moved.extend(self.move_frame_contents(&frame, &params.item, &moved).await);
+        }
+        self.update_connectors(&moved).await;
+
+        outcome
     }
 
-    async fn handle_delete_items(&self, id: ClientID, call: Call<DeleteItems>) {
+    async fn handle_patch_single_item(
+        &self,
+        id: ClientID,
+        call: Call<PatchSingleItem>,
+    ) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let selected = self.selected_items.get_async(&params.item_id).await;
+
+        let Some(selected) = selected else {
+            return handle.error(non_existent_id(params.item_id));
+        };
+
+        if selected.get() != &Some(id) {
+            return handle.error(resource_not_owned(params.item_id));
+        }
+
+        drop(selected);
+
+        debug!("Patching item {:?}", params.item_id);
+
+        let name = self.get_client(&id).await.get().info.name.clone();
+        // The item may have been deleted since it was checked
+        let Some(item) = self.canvas.get_ref(params.item_id).await else {
+            return handle.error(non_existent_id(params.item_id));
+        };
+
+        if let Some(err) = Self::check_revision(&item, params.expected_revision) {
+            return handle.err(err);
+        }
+
+        let frame = Self::frame_of(&item);
+        let revision = item.revision();
+        let mut patched = match item.patched(params.item_id, &params.patch) {
+            Ok(patched) => patched,
+            Err(reason) => return handle.error(reason),
+        };
+        let requested = serde_json::to_value(&patched).ok();
+        Self::keep_author(&item, &mut patched, &name);
+        drop(item);
+
+        if let Err(reason) = self.validate_item(&mut patched).await {
+            return handle.error(reason);
+        }
+        // Clients can only apply the patch themselves if the server left the result alone
+        let adjusted = requested != serde_json::to_value(&patched).ok();
+
+        let Some(mut item) = self.canvas.get_ref(params.item_id).await else {
+            return handle.error(non_existent_id(params.item_id));
+        };
+        // The item was released while it was validated
+        if let Some(err) = Self::check_revision(&item, Some(revision)) {
+            return handle.err(err);
+        }
+        *item = patched;
+        let (patched, revision) = (item.clone(), item.revision());
+        drop(item);
+
+        let outcome = handle.ok(());
+
+        if adjusted {
+            self.send_notify_c(SingleItemEdited {
+                id: params.item_id,
+                item: patched.clone(),
+                revision,
+            })
+            .await;
+        } else {
+            self.send_notify_c(SingleItemPatched {
+                id: params.item_id,
+                patch: params.patch,
+                revision,
+            })
+            .await;
+        }
+
+        let mut moved = BTreeSet::from([params.item_id]);
+        if let Some(frame) = frame {
+            moved.extend(self.move_frame_contents(&frame, &patched, &moved).await);
+        }
+        self.update_connectors(&moved).await;
+
+        outcome
+    }
+
+    async fn handle_delete_items(&self, id: ClientID, call: Call<DeleteItems>) -> CallOutcome {
         let (params, handle) = self.make_handle(id, call).await;
 
-        let mut removed = Vec::with_capacity(params.ids.len());
+        let requested = params.ids.len();
+        let mut removed = Vec::with_capacity(requested);
 
         for item_id in params.ids {
             if self.check_owned(&id, &handle, item_id).await {
@@ -245,21 +495,67 @@ impl Board {
 
         let mut client = self.get_client(&id).await;
         for item_id in removed.iter() {
-            client.get_mut().selection.items.remove(&item_id);
+            client.get_mut().selection.items.remove(item_id);
         }
         drop(client);
 
+        let mut detached = Vec::new();
         for &item_id in removed.iter() {
+            // Threads on a deleted item stay where the item was
+            if let Some(item) = self.canvas.get_item(item_id).await {
+                let position = item.bounds().min;
+                for thread in self.canvas.detach_threads(item_id, position).await {
+                    detached.push((thread, position));
+                }
+            }
             self.canvas.delete_item(item_id).await;
         }
 
-        handle.respond(());
+        // Items which couldn't be deleted have already been warned about
+        let outcome = match handle.respond(()) {
+            CallOutcome::Completed if removed.len() == requested => CallOutcome::Completed,
+            _ => CallOutcome::Failed,
+        };
 
         self.send_notify_c(ItemsDeleted { ids: removed }).await;
+
+        for (thread, position) in detached {
+            self.send_notify_c(ThreadMoved {
+                id: thread,
+                anchor: CommentAnchor::Position(position),
+            })
+            .await;
+        }
+
+        outcome
     }
 
-    async fn handle_create_item(&self, id: ClientID, call: Call<CreateItem>) {
-        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+    /// Give an edited sticky note the author it had before, or the name of the client which turned
+    /// another item into a note, so that clients can't claim someone else wrote it
+    fn keep_author(old: &Item, new: &mut Item, name: &str) {
+        let Item::StickyNote(note) = new else {
+            return;
+        };
+        note.author = match old {
+            Item::StickyNote(old) => old.author.clone(),
+            _ => name.to_string(),
+        };
+    }
+
+    async fn handle_create_item(&self, id: ClientID, call: Call<CreateItem>) -> CallOutcome {
+        let (mut params, handle) = call.create_handle(self.get_handle(&id).await);
+        if let Err(reason) = self.validate_item(&mut params.item).await {
+            return handle.error(reason);
+        }
+        match &mut params.item {
+            Item::Connector(connector) => {
+                self.attach_connector(connector).await;
+            }
+            Item::StickyNote(note) => {
+                note.author = self.get_client(&id).await.get().info.name.clone();
+            }
+            _ => {}
+        }
         let item_id = self.canvas.add_item(params.item.clone()).await;
 
         self.selected_items
@@ -267,23 +563,340 @@ impl Board {
             .await
             .unwrap(); // New ID was just created
 
-        handle.respond(item_id);
+        let outcome = handle.respond(item_id);
         self.send_notify_c(ItemCreated {
             client: id,
             id: item_id,
             item: params.item,
+            revision: 0,
+        })
+        .await;
+
+        outcome
+    }
+
+    async fn handle_import_svg(&self, id: ClientID, call: Call<ImportSvg>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+        let mut items = match import_svg(&params.url, params.position, &self.media_root).await {
+            Ok(items) => items,
+            Err(e) => return handle.err(e.into()),
+        };
+
+        for index in 0..items.len() {
+            if let Err(reason) = self.validate_item(&mut items[index]).await {
+                discard_import(&items, &self.media_root).await;
+                return handle.error(reason);
+            }
+        }
+
+        let mut ids = Vec::with_capacity(items.len());
+        for item in items {
+            let item_id = self.canvas.add_item(item.clone()).await;
+            self.selected_items
+                .insert_async(item_id, None)
+                .await
+                .expect("Item ID should be unique");
+            ids.push(item_id);
+
+            self.send_notify_c(ItemCreated {
+                client: id,
+                id: item_id,
+                item,
+                revision: 0,
+            })
+            .await;
+        }
+
+        handle.ok(ids)
+    }
+
+    async fn handle_table_insert_row(
+        &self,
+        id: ClientID,
+        call: Call<TableInsertRow>,
+    ) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let mut cells = params.cells.unwrap_or_default();
+        let edit = |table: &mut TableItem| {
+            table.insert_row(params.index as usize, cells.clone())?;
+            cells = table.cells[params.index as usize].clone();
+            Ok(())
+        };
+        let revision = self
+            .edit_table(id, handle, params.item_id, params.expected_revision, edit)
+            .await;
+
+        let Some(revision) = revision else {
+            return CallOutcome::Failed;
+        };
+
+        self.send_notify_c(TableRowInserted {
+            id: params.item_id,
+            index: params.index,
+            cells,
+            revision,
         })
         .await;
+
+        CallOutcome::Completed
     }
 
-    async fn handle_begin_path(&self, id: ClientID, call: Call<BeginPath>) {
+    async fn handle_table_delete_row(
+        &self,
+        id: ClientID,
+        call: Call<TableDeleteRow>,
+    ) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let edit = |table: &mut TableItem| table.delete_row(params.index as usize);
+        let revision = self
+            .edit_table(id, handle, params.item_id, params.expected_revision, edit)
+            .await;
+
+        let Some(revision) = revision else {
+            return CallOutcome::Failed;
+        };
+
+        self.send_notify_c(TableRowDeleted {
+            id: params.item_id,
+            index: params.index,
+            revision,
+        })
+        .await;
+
+        CallOutcome::Completed
+    }
+
+    async fn handle_table_insert_column(
+        &self,
+        id: ClientID,
+        call: Call<TableInsertColumn>,
+    ) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let edit = |table: &mut TableItem| table.insert_column(params.index as usize, params.width);
+        let revision = self
+            .edit_table(id, handle, params.item_id, params.expected_revision, edit)
+            .await;
+
+        let Some(revision) = revision else {
+            return CallOutcome::Failed;
+        };
+
+        self.send_notify_c(TableColumnInserted {
+            id: params.item_id,
+            index: params.index,
+            width: params.width,
+            revision,
+        })
+        .await;
+
+        CallOutcome::Completed
+    }
+
+    async fn handle_table_delete_column(
+        &self,
+        id: ClientID,
+        call: Call<TableDeleteColumn>,
+    ) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let edit = |table: &mut TableItem| table.delete_column(params.index as usize);
+        let revision = self
+            .edit_table(id, handle, params.item_id, params.expected_revision, edit)
+            .await;
+
+        let Some(revision) = revision else {
+            return CallOutcome::Failed;
+        };
+
+        self.send_notify_c(TableColumnDeleted {
+            id: params.item_id,
+            index: params.index,
+            revision,
+        })
+        .await;
+
+        CallOutcome::Completed
+    }
+
+    async fn handle_table_edit_cell(&self, id: ClientID, call: Call<TableEditCell>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let edit = |table: &mut TableItem| {
+            let (row, column) = (params.row as usize, params.column as usize);
+            table.set_cell(row, column, params.text.clone())
+        };
+        let revision = self
+            .edit_table(id, handle, params.item_id, params.expected_revision, edit)
+            .await;
+
+        let Some(revision) = revision else {
+            return CallOutcome::Failed;
+        };
+
+        self.send_notify_c(TableCellEdited {
+            id: params.item_id,
+            row: params.row,
+            column: params.column,
+            text: params.text,
+            revision,
+        })
+        .await;
+
+        CallOutcome::Completed
+    }
+
+    /// Produce a [`ErrorCode::BadData`] error if a comment has no content or is too long
+    fn check_comment_text(text: &str) -> Option<m::Error> {
+        let msg = if text.trim().is_empty() {
+            "Comments cannot be empty".to_string()
+        } else if text.len() > MAX_COMMENT_LENGTH {
+            format!("Comments cannot be longer than {MAX_COMMENT_LENGTH} bytes")
+        } else {
+            return None;
+        };
+        Some(m::Error {
+            code: ErrorCode::BadData,
+            msg: Some(msg),
+        })
+    }
+
+    async fn handle_create_thread(&self, id: ClientID, call: Call<CreateThread>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        if let CommentAnchor::Item(item_id) = params.anchor {
+            if self.canvas.get_item(item_id).await.is_none() {
+                return handle.error(non_existent_id(item_id));
+            }
+        }
+
+        if let Some(err) = Self::check_comment_text(&params.text) {
+            return handle.err(err);
+        }
+
+        let author = self.get_client(&id).await.get().info.name.clone();
+        let thread = self
+            .canvas
+            .add_thread(params.anchor, Comment::new(author, params.text))
+            .await;
+
+        let outcome = handle.ok(thread.id);
+
+        self.send_notify_c(ThreadCreated { thread, client: id })
+            .await;
+
+        outcome
+    }
+
+    async fn handle_reply_to_thread(&self, id: ClientID, call: Call<ReplyToThread>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        if let Some(err) = Self::check_comment_text(&params.text) {
+            return handle.err(err);
+        }
+
+        let author = self.get_client(&id).await.get().info.name.clone();
+        let comment = Comment::new(author, params.text);
+        let added = self
+            .canvas
+            .edit_thread(params.thread_id, |thread| {
+                thread.comments.push(comment.clone());
+                true
+            })
+            .await;
+
+        if added.is_none() {
+            return handle.error(non_existent_id(params.thread_id));
+        }
+
+        let outcome = handle.ok(());
+
+        self.send_notify_c(CommentAdded {
+            thread: params.thread_id,
+            comment,
+        })
+        .await;
+
+        outcome
+    }
+
+    /// Set whether a thread is resolved, returning whether it changed or [`None`] if there is no such thread
+    async fn set_thread_resolved<T: MethodType<Response = m::Result>>(
+        &self,
+        handle: MethodHandle<T>,
+        thread_id: ThreadID,
+        resolved: bool,
+    ) -> Option<bool> {
+        let changed = self
+            .canvas
+            .edit_thread(thread_id, |thread| {
+                std::mem::replace(&mut thread.resolved, resolved) != resolved
+            })
+            .await;
+
+        match changed {
+            Some(_) => handle.ok(()),
+            None => handle.error(non_existent_id(thread_id)),
+        };
+        changed
+    }
+
+    async fn handle_resolve_thread(&self, id: ClientID, call: Call<ResolveThread>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let Some(changed) = self
+            .set_thread_resolved(handle, params.thread_id, true)
+            .await
+        else {
+            return CallOutcome::Failed;
+        };
+
+        if changed {
+            self.send_notify_c(ThreadResolved {
+                id: params.thread_id,
+                client: id,
+            })
+            .await;
+        }
+
+        CallOutcome::Completed
+    }
+
+    async fn handle_reopen_thread(&self, id: ClientID, call: Call<ReopenThread>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let Some(changed) = self
+            .set_thread_resolved(handle, params.thread_id, false)
+            .await
+        else {
+            return CallOutcome::Failed;
+        };
+
+        if changed {
+            self.send_notify_c(ThreadReopened {
+                id: params.thread_id,
+                client: id,
+            })
+            .await;
+        }
+
+        CallOutcome::Completed
+    }
+
+    async fn handle_begin_path(&self, id: ClientID, call: Call<BeginPath>) -> CallOutcome {
+        let (mut params, handle) = call.create_handle(self.get_handle(&id).await);
+        if let Err(reason) = params.stroke.color.normalize() {
+            return handle.error(reason);
+        }
         let path = ActivePath {
             client: id,
             nodes: Vec::new(),
             listeners: Default::default(),
             stroke: params.stroke.clone(),
             last_flush: Instant::now(),
+            recognize_shapes: params.recognize_shapes.unwrap_or(false),
         };
 
         let path_id = PathID::new();
@@ -293,7 +906,13 @@ impl Board {
             .await
             .expect("PathIDs should always be unique");
 
-        handle.respond(path_id);
+        self.get_client(&id)
+            .await
+            .get_mut()
+            .active_paths
+            .push(path_id);
+
+        let outcome = handle.respond(path_id);
 
         self.send_notify_c(PathStarted {
             client: id,
@@ -301,9 +920,11 @@ impl Board {
             path: path_id,
         })
         .await;
+
+        outcome
     }
 
-    async fn handle_continue_path(&self, id: ClientID, call: Call<ContinuePath>) {
+    async fn handle_continue_path(&self, id: ClientID, call: Call<ContinuePath>) -> CallOutcome {
         let (mut params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let entry = self.active_paths.get_async(&params.path_id).await;
@@ -317,7 +938,7 @@ impl Board {
             return handle.error(resource_not_owned(params.path_id));
         }
 
-        handle.respond(());
+        let outcome = handle.respond(());
 
         for handle in &mut path.listeners {
             handle.add_items(&params.points);
@@ -336,9 +957,11 @@ impl Board {
 
             path.last_flush = now;
         }
+
+        outcome
     }
 
-    async fn handle_end_path(&self, id: ClientID, call: Call<EndPath>) {
+    async fn handle_end_path(&self, id: ClientID, call: Call<EndPath>) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let entry = self.active_paths.get_async(&params.path_id).await;
@@ -347,27 +970,45 @@ impl Board {
             return handle.error(non_existent_id(params.path_id));
         };
 
-        let path = entry.remove();
-
-        if path.client != id {
+        if entry.get().client != id {
             return handle.error(resource_not_owned(params.path_id));
         }
 
+        let path = entry.remove();
+
+        self.get_client(&id)
+            .await
+            .get_mut()
+            .active_paths
+            .retain(|&path_id| path_id != params.path_id);
+
         for handle in path.listeners {
             handle.finalize();
         }
 
-        if path.nodes.len() > 0 {
-            let item = PathItem {
-                transform: Transform::default(),
-                path: Spline { points: path.nodes },
-                stroke: path.stroke,
+        if !path.nodes.is_empty() {
+            let recognized = if params.recognize_shapes.unwrap_or(path.recognize_shapes) {
+                let points: Vec<_> = path.nodes.iter().map(|node| node.position).collect();
+                recognize_shape(&points, &path.stroke)
+            } else {
+                None
+            };
+
+            let (shape, item) = match recognized {
+                Some((shape, item)) => (Some(shape), item),
+                None => {
+                    let item = PathItem {
+                        transform: Transform::default(),
+                        path: Spline { points: path.nodes }.simplify(self.path_tolerance),
+                        stroke: path.stroke,
+                        start_marker: Marker::None,
+                        end_marker: Marker::None,
+                    };
+                    (None, item.to_item())
+                }
             };
 
-            let item_id = self
-                .canvas
-                .add_item(crate::canvas::Item::Path(item.clone()))
-                .await;
+            let item_id = self.canvas.add_item(item.clone()).await;
 
             self.selected_items
                 .insert_async(item_id, None)
@@ -377,29 +1018,107 @@ impl Board {
             self.send_notify_c(ItemCreated {
                 client: id,
                 id: item_id,
-                item: item.to_item(),
+                item,
+                revision: 0,
             })
             .await;
 
-            handle.ok(item_id);
+            handle.ok(EndedPath { item_id, shape })
         } else {
-            handle.err(ErrorCode::EmptyPath.into());
+            handle.err(ErrorCode::EmptyPath.into())
         }
     }
 
-    async fn handle_get_all_item_ids(&self, id: ClientID, call: Call<GetAllItemIDs>) {
+    async fn handle_get_all_item_ids(
+        &self,
+        id: ClientID,
+        call: Call<GetAllItemIDs>,
+    ) -> CallOutcome {
         let (_, handle) = call.create_handle(self.get_handle(&id).await);
         let ids = self.canvas.get_item_ids().await;
-        handle.respond(ids);
+        handle.respond(ids)
+    }
+
+    async fn handle_get_items_at_point(
+        &self,
+        id: ClientID,
+        call: Call<GetItemsAtPoint>,
+    ) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+        let tolerance = params.tolerance.unwrap_or(0.0).max(0.0);
+        let ids = self.canvas.get_items_at(params.point, tolerance).await;
+        handle.respond(ids)
+    }
+
+    async fn handle_get_frames(&self, id: ClientID, call: Call<GetFrames>) -> CallOutcome {
+        let (_, handle) = call.create_handle(self.get_handle(&id).await);
+        let mut frames = Vec::new();
+        self.canvas
+            .scan_items(|id, item| {
+                if let Item::Frame(frame) = item {
+                    frames.push(FrameInfo {
+                        id,
+                        name: frame.name.clone(),
+                        transform: frame.transform.clone(),
+                    });
+                }
+            })
+            .await;
+        frames.sort_by_key(|frame| frame.id);
+        handle.respond(frames)
+    }
+
+    async fn handle_get_all_threads(&self, id: ClientID, call: Call<GetAllThreads>) -> CallOutcome {
+        let (_, handle) = call.create_handle(self.get_handle(&id).await);
+        handle.respond(self.canvas.get_threads().await)
+    }
+
+    async fn handle_follow_link(&self, id: ClientID, call: Call<FollowLink>) -> CallOutcome {
+        let (params, handle) = call.create_handle(self.get_handle(&id).await);
+
+        let Some(item) = self.canvas.get_item(params.item_id).await else {
+            return handle.error(non_existent_id(params.item_id));
+        };
+
+        let Item::Link(link) = &item else {
+            return handle.error(RejectReason::IncorrectType {
+                key: Some(params.item_id.to_string()),
+                expected: "Link",
+                received: item.type_name().to_string(),
+            });
+        };
+
+        let Some(mut target) = link.target.clone() else {
+            return handle.err(m::Error {
+                code: ErrorCode::BadData,
+                msg: Some("Link does not lead to a board".to_string()),
+            });
+        };
+
+        match self.boards.resolve_link(&mut target).await {
+            Ok(()) => handle.ok(target),
+            Err(_) => handle.err(m::Error {
+                code: ErrorCode::NotFound,
+                msg: Some("Link leads to a board or item which no longer exists".to_string()),
+            }),
+        }
     }
 
-    async fn handle_get_all_client_ids(&self, id: ClientID, call: Call<GetAllClientIDs>) {
+    async fn handle_get_all_client_ids(
+        &self,
+        id: ClientID,
+        call: Call<GetAllClientIDs>,
+    ) -> CallOutcome {
         let (_, handle) = call.create_handle(self.get_handle(&id).await);
         let ids = self.client_ids.read().await.iter().cloned().collect();
-        handle.respond(ids);
+        handle.respond(ids)
     }
 
-    async fn handle_get_client_state(&self, id: ClientID, call: Call<GetClientState>) {
+    async fn handle_get_client_state(
+        &self,
+        id: ClientID,
+        call: Call<GetClientState>,
+    ) -> CallOutcome {
         let (params, handle) = call.create_handle(self.get_handle(&id).await);
 
         let target = self.clients.get_async(&params.client_id).await;
@@ -420,8 +1139,9 @@ impl Board {
                 .map(|(&a, b)| (a, b.clone()))
                 .collect(),
             selection_transform: target.selection.own_transform.clone(),
+            latency: target.latency.map(|latency| latency.as_secs_f64() * 1000.0),
         };
 
-        handle.respond(result);
+        handle.respond(result)
     }
 }
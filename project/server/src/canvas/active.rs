@@ -1,40 +1,125 @@
 //! An implementation of a currently active canvas
 
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicU32, Ordering},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
 };
 
 use scc::hash_map::OccupiedEntry;
 use tokio::sync::RwLock;
 
-use crate::{message::ItemID, utils::CounterU64};
+use crate::{
+    message::{ItemID, ThreadID},
+    utils::CounterU64,
+};
 
-use super::Item;
+use super::{
+    comment::{Comment, CommentAnchor, CommentThread},
+    Item, Point,
+};
 
 /// An open canvas
 pub struct ActiveCanvas {
     next_id: AtomicU32,
     item_ids: RwLock<BTreeSet<ItemID>>,
-    items: scc::HashMap<ItemID, Item>,
+    items: scc::HashMap<ItemID, StoredItem>,
+    /// The connectors attached to each item, see [`ActiveCanvas::get_connectors`]
+    connectors: Mutex<BTreeMap<ItemID, BTreeSet<ItemID>>>,
+    next_thread_id: AtomicU32,
+    threads: RwLock<BTreeMap<ThreadID, CommentThread>>,
     edit_count: CounterU64,
+    /// The state before any changes which may still be undone, see [`ActiveCanvas::begin_undo`]
+    undo_log: Mutex<Option<UndoLog>>,
+    /// See [`ActiveCanvas::batch_lock`]
+    batch_lock: RwLock<()>,
+}
+
+/// An item along with the number of times it has been changed
+#[derive(Debug, Clone)]
+struct StoredItem {
+    item: Item,
+    revision: u32,
+}
+
+impl StoredItem {
+    fn new(item: Item) -> Self {
+        Self { item, revision: 0 }
+    }
+}
+
+/// The items and comment threads changed since the log was started, as they were before the first change
+///
+/// `None` means it did not exist yet
+#[derive(Default)]
+struct UndoLog {
+    items: BTreeMap<ItemID, Option<StoredItem>>,
+    threads: BTreeMap<ThreadID, Option<CommentThread>>,
 }
 
 /// A lock-holding reference to an item on the board
-pub struct ItemRef<'a>(OccupiedEntry<'a, ItemID, Item>, &'a CounterU64);
+pub struct ItemRef<'a> {
+    entry: OccupiedEntry<'a, ItemID, StoredItem>,
+    canvas: &'a ActiveCanvas,
+    /// The items the item was attached to when the reference was taken, if it is a connector
+    ends: Option<(ItemID, ItemID)>,
+    /// Whether the item has been borrowed mutably through this reference
+    edited: bool,
+}
+
+impl<'a> ItemRef<'a> {
+    /// The number of times the item has been changed
+    ///
+    /// Every reference which borrows the item mutably counts as one change, including changes which
+    /// follow from other edits (such as connectors moving with their items)
+    pub fn revision(&self) -> u32 {
+        self.entry.get().revision
+    }
+
+    fn record_undo(&self) {
+        self.canvas
+            .record_item(*self.entry.key(), || Some(self.entry.get().clone()));
+    }
+}
 
 impl<'a> Deref for ItemRef<'a> {
     type Target = Item;
     fn deref(&self) -> &Self::Target {
-        self.0.get()
+        &self.entry.get().item
     }
 }
 
 impl<'a> DerefMut for ItemRef<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.1.next();
-        self.0.get_mut()
+        if !self.edited {
+            self.edited = true;
+            self.canvas.edit_count.next();
+            self.record_undo();
+            self.entry.get_mut().revision += 1;
+        }
+        &mut self.entry.get_mut().item
+    }
+}
+
+impl<'a> Drop for ItemRef<'a> {
+    fn drop(&mut self) {
+        let ends = connector_ends(&self.entry.get().item);
+        if ends != self.ends {
+            let id = *self.entry.key();
+            self.canvas.unindex_connector(id, self.ends);
+            self.canvas.index_connector(id, ends);
+        }
+    }
+}
+
+/// The items a connector is attached to
+fn connector_ends(item: &Item) -> Option<(ItemID, ItemID)> {
+    match item {
+        Item::Connector(connector) => Some((connector.source, connector.target)),
+        _ => None,
     }
 }
 
@@ -45,30 +130,57 @@ impl ActiveCanvas {
             next_id: AtomicU32::new(1),
             item_ids: Default::default(),
             items: Default::default(),
+            connectors: Default::default(),
+            next_thread_id: AtomicU32::new(1),
+            threads: Default::default(),
             edit_count: CounterU64::new(),
+            undo_log: Default::default(),
+            batch_lock: Default::default(),
         }
     }
 
+    /// Held exclusively by atomic batches while their changes may still be undone, and shared by
+    /// everything else that changes the canvas or saves it
+    pub fn batch_lock(&self) -> &RwLock<()> {
+        &self.batch_lock
+    }
+
     fn get_id(&self) -> ItemID {
         let val = self.next_id.fetch_add(1, Ordering::Relaxed);
         ItemID(val)
     }
 
     /// Get a reference to an item on the canvas
-    pub async fn get_ref(&self, id: ItemID) -> Option<ItemRef> {
-        Some(ItemRef(self.items.get_async(&id).await?, &self.edit_count))
+    pub async fn get_ref(&self, id: ItemID) -> Option<ItemRef<'_>> {
+        let entry = self.items.get_async(&id).await?;
+        let ends = connector_ends(&entry.get().item);
+        Some(ItemRef {
+            entry,
+            canvas: self,
+            ends,
+            edited: false,
+        })
     }
 
     /// Retrieve the specified item if present
     pub async fn get_item(&self, id: ItemID) -> Option<Item> {
-        Some(self.items.get_async(&id).await?.get().clone())
+        Some(self.items.get_async(&id).await?.get().item.clone())
+    }
+
+    /// Retrieve the specified item and its revision if present
+    pub async fn get_item_revision(&self, id: ItemID) -> Option<(Item, u32)> {
+        let entry = self.items.get_async(&id).await?;
+        let stored = entry.get();
+        Some((stored.item.clone(), stored.revision))
     }
 
     /// Insert a new item on the canvas and return an ID for it
     pub async fn add_item(&self, item: Item) -> ItemID {
         let id = self.get_id();
+        self.record_item(id, || None);
+        self.index_connector(id, connector_ends(&item));
         self.items
-            .insert_async(id, item)
+            .insert_async(id, StoredItem::new(item))
             .await
             .expect("Duplicate Item ID, something is wrong");
         self.item_ids.write().await.insert(id);
@@ -78,22 +190,156 @@ impl ActiveCanvas {
 
     /// Remove the item from the canvas if it exists
     pub async fn delete_item(&self, id: ItemID) {
-        self.items.remove_async(&id).await;
+        if let Some((_, stored)) = self.items.remove_async(&id).await {
+            self.unindex_connector(id, connector_ends(&stored.item));
+            self.record_item(id, || Some(stored));
+            self.edit_count.next();
+        }
+    }
+
+    /// The number of changes that have been made to the canvas, for detecting modifications
+    pub fn edit_count(&self) -> u64 {
+        self.edit_count.get()
+    }
+
+    /// Continue counting edits from a previous session, see [`Self::edit_count`]
+    pub fn set_edit_count(&mut self, edit_count: u64) {
+        self.edit_count = CounterU64::starting_at(edit_count);
     }
 
     /// Insert a new item synchronously from an exclusive reference
     pub fn add_item_owned(&mut self, item: Item) -> ItemID {
         let id = self.get_id();
+        self.index_connector(id, connector_ends(&item));
         self.items
-            .insert(id, item)
+            .insert(id, StoredItem::new(item))
             .expect("Duplicate Item ID, something is wrong");
         self.item_ids.get_mut().insert(id);
         id
     }
 
+    /// Insert an item with a known ID synchronously from an exclusive reference, such as one loaded from a file
+    pub fn add_item_owned_with_id(&mut self, id: ItemID, item: Item) {
+        self.index_connector(id, connector_ends(&item));
+        self.items
+            .insert(id, StoredItem::new(item))
+            .expect("Duplicate Item ID, something is wrong");
+        self.item_ids.get_mut().insert(id);
+        let next_id = self.next_id.get_mut();
+        *next_id = (*next_id).max(id.0 + 1);
+    }
+
+    fn index_connector(&self, id: ItemID, ends: Option<(ItemID, ItemID)>) {
+        let Some((source, target)) = ends else { return };
+        let mut connectors = self.connectors.lock().unwrap();
+        for end in [source, target] {
+            connectors.entry(end).or_default().insert(id);
+        }
+    }
+
+    fn unindex_connector(&self, id: ItemID, ends: Option<(ItemID, ItemID)>) {
+        let Some((source, target)) = ends else { return };
+        let mut connectors = self.connectors.lock().unwrap();
+        for end in [source, target] {
+            if let Some(attached) = connectors.get_mut(&end) {
+                attached.remove(&id);
+                if attached.is_empty() {
+                    connectors.remove(&end);
+                }
+            }
+        }
+    }
+
+    /// Get the IDs of every connector attached to any of the items
+    pub fn get_connectors(&self, items: &BTreeSet<ItemID>) -> BTreeSet<ItemID> {
+        let connectors = self.connectors.lock().unwrap();
+        items
+            .iter()
+            .filter_map(|id| connectors.get(id))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Start a new comment thread
+    pub async fn add_thread(&self, anchor: CommentAnchor, comment: Comment) -> CommentThread {
+        let id = ThreadID(self.next_thread_id.fetch_add(1, Ordering::Relaxed));
+        let thread = CommentThread {
+            id,
+            anchor,
+            comments: vec![comment],
+            resolved: false,
+        };
+        self.record_thread(id, || None);
+        self.threads.write().await.insert(id, thread.clone());
+        self.edit_count.next();
+        thread
+    }
+
+    /// Insert an existing comment thread synchronously from an exclusive reference
+    pub fn add_thread_owned(&mut self, thread: CommentThread) {
+        let next_id = self.next_thread_id.get_mut();
+        *next_id = (*next_id).max(thread.id.0 + 1);
+        self.threads.get_mut().insert(thread.id, thread);
+    }
+
+    /// Run the provided callback on a comment thread if it exists, returning whether the callback changed it
+    pub async fn edit_thread(
+        &self,
+        id: ThreadID,
+        f: impl FnOnce(&mut CommentThread) -> bool,
+    ) -> Option<bool> {
+        let mut threads = self.threads.write().await;
+        let thread = threads.get_mut(&id)?;
+        self.record_thread(id, || Some(thread.clone()));
+        let changed = f(thread);
+        if changed {
+            self.edit_count.next();
+        }
+        Some(changed)
+    }
+
+    /// Get a copy of every comment thread, in the order they were started
+    pub async fn get_threads(&self) -> Vec<CommentThread> {
+        self.threads.read().await.values().cloned().collect()
+    }
+
+    /// Pin every thread attached to an item to a fixed position instead, returning the IDs of those moved
+    pub async fn detach_threads(&self, item: ItemID, position: Point) -> Vec<ThreadID> {
+        let mut moved = Vec::new();
+        for thread in self.threads.write().await.values_mut() {
+            if matches!(thread.anchor, CommentAnchor::Item(id) if id == item) {
+                self.record_thread(thread.id, || Some(thread.clone()));
+                thread.anchor = CommentAnchor::Position(position);
+                moved.push(thread.id);
+            }
+        }
+        if !moved.is_empty() {
+            self.edit_count.next();
+        }
+        moved
+    }
+
     /// Run the provided callback on each item in the canvas
     pub async fn scan_items(&self, mut f: impl FnMut(ItemID, &Item)) {
-        self.items.scan_async(|&id, item| f(id, item)).await
+        self.items
+            .scan_async(|&id, stored| f(id, &stored.item))
+            .await
+    }
+
+    /// Get the IDs of every item within `tolerance` of a point, the most recently created first
+    ///
+    /// Every item on the board is checked, since there is no spatial index yet
+    pub async fn get_items_at(&self, point: Point, tolerance: f64) -> Vec<ItemID> {
+        let mut ids = Vec::new();
+        self.scan_items(|id, item| {
+            if item.contains_point(point, tolerance) {
+                ids.push(id);
+            }
+        })
+        .await;
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        ids
     }
 
     /// Get a vector of all current Item IDs
@@ -102,8 +348,75 @@ impl ActiveCanvas {
     }
 
     /// Try to read the current ItemIDs without blocking
-    pub fn get_item_ids_sync(&self) -> Result<Vec<ItemID>, ()> {
-        let ids = self.item_ids.try_read().or(Err(()))?;
-        Ok(ids.iter().cloned().collect())
+    pub fn get_item_ids_sync(&self) -> Option<Vec<ItemID>> {
+        let ids = self.item_ids.try_read().ok()?;
+        Some(ids.iter().cloned().collect())
+    }
+
+    /// Start recording changes so that they can be undone with [`Self::undo`]
+    pub fn begin_undo(&self) {
+        *self.undo_log.lock().unwrap() = Some(UndoLog::default());
+    }
+
+    /// Stop recording changes, keeping those made since [`Self::begin_undo`]
+    pub fn end_undo(&self) {
+        *self.undo_log.lock().unwrap() = None;
+    }
+
+    fn record_item(&self, id: ItemID, before: impl FnOnce() -> Option<StoredItem>) {
+        if let Some(log) = self.undo_log.lock().unwrap().as_mut() {
+            log.items.entry(id).or_insert_with(before);
+        }
+    }
+
+    fn record_thread(&self, id: ThreadID, before: impl FnOnce() -> Option<CommentThread>) {
+        if let Some(log) = self.undo_log.lock().unwrap().as_mut() {
+            log.threads.entry(id).or_insert_with(before);
+        }
+    }
+
+    /// Return everything changed since [`Self::begin_undo`] to how it was, and stop recording
+    ///
+    /// Returns the ID of each item put back and whether it existed before the changes
+    pub async fn undo(&self) -> Vec<(ItemID, bool)> {
+        let Some(log) = self.undo_log.lock().unwrap().take() else {
+            return Vec::new();
+        };
+        if log.items.is_empty() && log.threads.is_empty() {
+            return Vec::new();
+        }
+
+        let mut restored = Vec::with_capacity(log.items.len());
+        let mut item_ids = self.item_ids.write().await;
+        for (id, before) in log.items {
+            let current = self.items.remove_async(&id).await;
+            if let Some((_, current)) = current {
+                self.unindex_connector(id, connector_ends(&current.item));
+            }
+            restored.push((id, before.is_some()));
+            match before {
+                Some(stored) => {
+                    self.index_connector(id, connector_ends(&stored.item));
+                    let _ = self.items.insert_async(id, stored).await;
+                    item_ids.insert(id);
+                }
+                None => {
+                    item_ids.remove(&id);
+                }
+            }
+        }
+        drop(item_ids);
+
+        let mut threads = self.threads.write().await;
+        for (id, before) in log.threads {
+            match before {
+                Some(thread) => threads.insert(id, thread),
+                None => threads.remove(&id),
+            };
+        }
+        drop(threads);
+
+        self.edit_count.next();
+        restored
     }
 }
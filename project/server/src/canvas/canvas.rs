@@ -1,28 +1,219 @@
 //! Collection of types relating to board objects
 
 pub mod active;
+pub mod comment;
+pub mod geometry;
 pub mod item;
+pub mod recognize;
+
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "codegen")]
 use ts_rs::TS;
 
+use crate::message::reject::RejectReason;
+
 pub use active::ActiveCanvas;
 pub use item::Item;
+pub use recognize::RecognizedShape;
+
+/// The number of pixels the client draws per board unit, used for text and images
+pub const PX_PER_UNIT: f64 = 37.8;
+
+/// The font size (in pixels) of text items
+pub const FONT_SIZE: f64 = 16.0;
 
 /// A global location on the board plane
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
 #[cfg_attr(feature = "codegen", derive(TS))]
 pub struct Point {
-    x: f64,
-    y: f64,
+    /// The horizontal position
+    pub x: f64,
+    /// The vertical position
+    pub y: f64,
+}
+
+impl Point {
+    /// Create a point from its coordinates
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// An axis-aligned rectangular area of the board
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct Rect {
+    /// The top-left corner
+    pub min: Point,
+    /// The bottom-right corner
+    pub max: Point,
+}
+
+impl Rect {
+    /// Create a rectangle from its top-left corner and size
+    pub fn from_size(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            min: Point::new(x, y),
+            max: Point::new(x + width, y + height),
+        }
+    }
+
+    /// The smallest rectangle containing every point, or [`None`] if there are no points
+    pub fn from_points(points: impl IntoIterator<Item = Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |rect, p| rect.union(&Self::from_point(p)),
+        ))
+    }
+
+    /// The smallest rectangle containing both rectangles
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Point::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Point::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    /// Grow the rectangle by `margin` in every direction
+    pub fn expand(&self, margin: f64) -> Self {
+        Self {
+            min: Point::new(self.min.x - margin, self.min.y - margin),
+            max: Point::new(self.max.x + margin, self.max.y + margin),
+        }
+    }
+
+    /// A rectangle covering only a single point
+    pub fn from_point(p: Point) -> Self {
+        Self { min: p, max: p }
+    }
+
+    /// Whether the point is inside the rectangle or on its edge
+    pub fn contains(&self, p: Point) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x && self.min.y <= p.y && p.y <= self.max.y
+    }
+
+    /// Whether the rectangles overlap
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    #[allow(missing_docs)]
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    #[allow(missing_docs)]
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
 }
 
 /// A CSS-compatible color
+///
+/// Clients may use any of the formats accepted by [`Rgba::from_str`], which the server rewrites as hex
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[cfg_attr(feature = "codegen", derive(TS))]
 pub struct Color(String);
 
+/// The longest string that will be parsed as a color, well beyond any valid one
+const MAX_COLOR_LENGTH: usize = 64;
+
+impl Color {
+    /// The color as a CSS value
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The components of the color, or [`None`] if it isn't valid
+    pub fn parse(&self) -> Option<Rgba> {
+        self.0.parse().ok()
+    }
+
+    /// Check that the color is valid and rewrite it in the same form as [`Color::from_rgba`]
+    pub fn normalize(&mut self) -> Result<(), RejectReason> {
+        let Some(rgba) = self.parse() else {
+            return Err(RejectReason::IncorrectType {
+                key: Some("color".to_string()),
+                expected: "Color",
+                received: self.0.chars().take(MAX_COLOR_LENGTH).collect(),
+            });
+        };
+        *self = rgba.into();
+        Ok(())
+    }
+
+    /// Rewrite the color like [`Color::normalize`], replacing it with `fallback` if it isn't valid
+    ///
+    /// Used for colors saved before they were checked, which may not be valid
+    pub fn normalize_or(&mut self, fallback: Color) {
+        if self.normalize().is_err() {
+            *self = fallback;
+        }
+    }
+
+    /// A color from its components, written as a hex string
+    pub fn from_rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        if alpha == u8::MAX {
+            Self(format!("#{red:02x}{green:02x}{blue:02x}"))
+        } else {
+            Self(format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}"))
+        }
+    }
+}
+
+/// The components of a [`Color`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub struct Rgba {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+impl FromStr for Rgba {
+    type Err = ();
+
+    /// Parse a CSS color: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, `rgb()`, `rgba()`, `hsl()`, `hsla()` or a name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_COLOR_LENGTH {
+            return Err(());
+        }
+        // The only name added since the SVG color keywords
+        if s.trim().eq_ignore_ascii_case("rebeccapurple") {
+            return Ok(Self {
+                red: 0x66,
+                green: 0x33,
+                blue: 0x99,
+                alpha: u8::MAX,
+            });
+        }
+        let color = svgtypes::Color::from_str(s).map_err(|_| ())?;
+        Ok(Self {
+            red: color.red,
+            green: color.green,
+            blue: color.blue,
+            alpha: color.alpha,
+        })
+    }
+}
+
+impl From<Rgba> for Color {
+    fn from(rgba: Rgba) -> Self {
+        Self::from_rgba(rgba.red, rgba.green, rgba.blue, rgba.alpha)
+    }
+}
+
 /// A descriptor for how to render a line
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[cfg_attr(feature = "codegen", derive(TS))]
@@ -31,6 +222,321 @@ pub struct Stroke {
     pub width: f64,
     /// The color of the line
     pub color: Color,
+    /// The lengths of alternating dashes and gaps, in the same units as the width.
+    /// A solid line if empty
+    #[serde(default)]
+    pub dash: Vec<f64>,
+    /// The shape of the ends of the line
+    #[serde(default)]
+    pub cap: LineCap,
+    /// The shape of the corners of the line
+    #[serde(default)]
+    pub join: LineJoin,
+    /// How opaque the line is, from 0 to 1
+    #[serde(default = "full_opacity")]
+    pub opacity: f64,
+}
+
+fn full_opacity() -> f64 {
+    1.0
+}
+
+/// The most lengths the dash pattern of a [`Stroke`] may have
+pub const MAX_DASH_LENGTHS: usize = 64;
+
+impl Stroke {
+    /// Check that the width isn't negative, the opacity is between 0 and 1 and that the dash lengths are usable
+    pub fn check(&self) -> Result<(), RejectReason> {
+        if !(self.width.is_finite() && self.width >= 0.0) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("width".to_string()),
+                expected: "non-negative number",
+                received: self.width.to_string(),
+            });
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("opacity".to_string()),
+                expected: "number from 0 to 1",
+                received: self.opacity.to_string(),
+            });
+        }
+        if self.dash.len() > MAX_DASH_LENGTHS {
+            return Err(RejectReason::IncorrectType {
+                key: Some("dash".to_string()),
+                expected: "at most 64 lengths",
+                received: self.dash.len().to_string(),
+            });
+        }
+        if let Some(length) = self
+            .dash
+            .iter()
+            .find(|length| !(length.is_finite() && **length >= 0.0))
+        {
+            return Err(RejectReason::IncorrectType {
+                key: Some("dash".to_string()),
+                expected: "non-negative number",
+                received: length.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The shape drawn at the ends of a line, matching the SVG `stroke-linecap` values
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum LineCap {
+    /// The line stops exactly at its end
+    #[default]
+    Butt,
+    /// A half circle past the end
+    Round,
+    /// A half square past the end
+    Square,
+}
+
+/// The shape drawn where parts of a line meet, matching the SVG `stroke-linejoin` values
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum LineJoin {
+    /// A sharp corner
+    #[default]
+    Miter,
+    /// A rounded corner
+    Round,
+    /// A corner with its tip cut off
+    Bevel,
+}
+
+/// How to paint the inside of a shape
+///
+/// A plain [`Color`] is written as a string, so fills from before gradients existed still load
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(untagged)]
+pub enum Fill {
+    /// A single color
+    Solid(Color),
+    /// A blend between colors
+    Gradient(Gradient),
+}
+
+impl From<Color> for Fill {
+    fn from(color: Color) -> Self {
+        Self::Solid(color)
+    }
+}
+
+/// The most stops a [`Gradient`] may have
+pub const MAX_GRADIENT_STOPS: usize = 64;
+
+impl Fill {
+    /// Check that a gradient has a usable number of stops, within its length, and a non-negative radius
+    pub fn check(&self) -> Result<(), RejectReason> {
+        let Self::Gradient(gradient) = self else {
+            return Ok(());
+        };
+        let stops = match gradient {
+            Gradient::Linear { stops, .. } => stops,
+            Gradient::Radial { radius, stops, .. } => {
+                if !(radius.is_finite() && *radius >= 0.0) {
+                    return Err(RejectReason::IncorrectType {
+                        key: Some("radius".to_string()),
+                        expected: "non-negative number",
+                        received: radius.to_string(),
+                    });
+                }
+                stops
+            }
+        };
+        if stops.len() > MAX_GRADIENT_STOPS {
+            return Err(RejectReason::IncorrectType {
+                key: Some("stops".to_string()),
+                expected: "at most 64 stops",
+                received: stops.len().to_string(),
+            });
+        }
+        if let Some(stop) = stops
+            .iter()
+            .find(|stop| !(0.0..=1.0).contains(&stop.offset))
+        {
+            return Err(RejectReason::IncorrectType {
+                key: Some("offset".to_string()),
+                expected: "number from 0 to 1",
+                received: stop.offset.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Every color used by the fill
+    pub fn colors_mut(&mut self) -> Vec<&mut Color> {
+        match self {
+            Self::Solid(color) => vec![color],
+            Self::Gradient(Gradient::Linear { stops, .. } | Gradient::Radial { stops, .. }) => {
+                stops.iter_mut().map(|stop| &mut stop.color).collect()
+            }
+        }
+    }
+}
+
+/// A blend between colors
+///
+/// Positions are fractions of the shape's bounding box, from `(0, 0)` at the top-left corner
+/// to `(1, 1)` at the bottom-right corner
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(tag = "type")]
+pub enum Gradient {
+    /// Colors change along the line from `start` to `end`
+    Linear {
+        #[allow(missing_docs)]
+        start: Point,
+        #[allow(missing_docs)]
+        end: Point,
+        #[allow(missing_docs)]
+        stops: Vec<GradientStop>,
+    },
+    /// Colors change with the distance from `center`, reaching the last stop at `radius`
+    Radial {
+        #[allow(missing_docs)]
+        center: Point,
+        #[allow(missing_docs)]
+        radius: f64,
+        #[allow(missing_docs)]
+        stops: Vec<GradientStop>,
+    },
+}
+
+/// A color at a position along a [`Gradient`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct GradientStop {
+    /// How far along the gradient the color is, from 0 to 1
+    pub offset: f64,
+    #[allow(missing_docs)]
+    pub color: Color,
+}
+
+/// How text is drawn
+///
+/// Text is placed relative to the origin of its item's [`Transform`], as chosen by `align` and `anchor`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase", default)]
+pub struct TextStyle {
+    /// A CSS font family, such as `serif` or `Arial`
+    pub font_family: String,
+    /// The size of the font in pixels, before the item's [`Transform`] is applied
+    pub font_size: f64,
+    #[allow(missing_docs)]
+    pub color: Color,
+    /// Which side of the origin the lines are lined up on
+    pub align: TextAlign,
+    /// Which part of the text is placed at the origin vertically
+    pub anchor: TextAnchor,
+}
+
+/// The largest font size (in pixels) accepted for text
+const MAX_FONT_SIZE: f64 = FONT_SIZE * 64.0;
+
+/// The longest font family accepted, well beyond any real font name
+const MAX_FONT_FAMILY_LENGTH: usize = 128;
+
+impl TextStyle {
+    /// Check that the font size is positive and not too large, and that the font family isn't too long
+    pub fn check(&self) -> Result<(), RejectReason> {
+        if !(self.font_size > 0.0 && self.font_size <= MAX_FONT_SIZE) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("fontSize".to_string()),
+                expected: "positive number up to 1024",
+                received: self.font_size.to_string(),
+            });
+        }
+        if self.font_family.len() > MAX_FONT_FAMILY_LENGTH {
+            return Err(RejectReason::IncorrectType {
+                key: Some("fontFamily".to_string()),
+                expected: "font family of at most 128 bytes",
+                received: self
+                    .font_family
+                    .chars()
+                    .take(MAX_FONT_FAMILY_LENGTH)
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font_family: "sans-serif".to_string(),
+            font_size: FONT_SIZE,
+            color: Color::from_rgba(0, 0, 0, u8::MAX),
+            align: TextAlign::default(),
+            anchor: TextAnchor::default(),
+        }
+    }
+}
+
+/// The horizontal alignment of text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum TextAlign {
+    /// Lines start at the origin
+    Left,
+    /// Lines are centred on the origin
+    #[default]
+    Center,
+    /// Lines end at the origin
+    Right,
+}
+
+/// The vertical placement of text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum TextAnchor {
+    /// The top of the first line is at the origin
+    Top,
+    /// The text is centred on the origin
+    #[default]
+    Middle,
+    /// The bottom of the last line is at the origin
+    Bottom,
+}
+
+/// A decoration drawn at the end of a line
+///
+/// Every marker fits in a square of [`Marker::size`] with one side centred on the end of the line
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum Marker {
+    /// A plain end
+    #[default]
+    None,
+    /// A filled triangle pointing away from the line
+    Arrow,
+    /// Two strokes meeting at the end of the line
+    OpenArrow,
+    /// A filled circle
+    Circle,
+    /// A filled square standing on one corner
+    Diamond,
+    /// A stroke across the end of the line
+    Bar,
+}
+
+impl Marker {
+    /// The length of the marker along the line, which is also its width, for a line of the given thickness
+    pub fn size(&self, width: f64) -> f64 {
+        match self {
+            Marker::None => 0.0,
+            Marker::Circle => width * 3.0,
+            Marker::Arrow | Marker::OpenArrow | Marker::Diamond | Marker::Bar => width * 4.0,
+        }
+    }
 }
 
 /// An angle, measured in degrees clockwise
@@ -53,6 +559,61 @@ pub struct Transform {
     pub basis_y: Point,
 }
 
+impl Transform {
+    /// Map a point from the object's own space onto the board
+    pub fn apply(&self, p: Point) -> Point {
+        let v = self.apply_vector(p);
+        Point::new(self.origin.x + v.x, self.origin.y + v.y)
+    }
+
+    /// Map a direction from the object's own space, ignoring the origin
+    pub fn apply_vector(&self, v: Point) -> Point {
+        Point::new(
+            self.basis_x.x * v.x + self.basis_y.x * v.y,
+            self.basis_x.y * v.x + self.basis_y.y * v.y,
+        )
+    }
+
+    /// The transform which applies `inner` and then `self`
+    pub fn compose(&self, inner: &Transform) -> Transform {
+        Transform {
+            origin: self.apply(inner.origin),
+            basis_x: self.apply_vector(inner.basis_x),
+            basis_y: self.apply_vector(inner.basis_y),
+        }
+    }
+
+    /// The factor areas are scaled by
+    pub fn determinant(&self) -> f64 {
+        self.basis_x.x * self.basis_y.y - self.basis_x.y * self.basis_y.x
+    }
+
+    /// The average factor lengths are scaled by
+    pub fn scale_factor(&self) -> f64 {
+        self.determinant().abs().sqrt()
+    }
+
+    /// The transform which undoes this one, or [`None`] if it flattens the plane
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let basis_x = Point::new(self.basis_y.y / det, -self.basis_x.y / det);
+        let basis_y = Point::new(-self.basis_y.x / det, self.basis_x.x / det);
+        let inverse = Transform {
+            origin: Point::default(),
+            basis_x,
+            basis_y,
+        };
+        let origin = inverse.apply_vector(self.origin);
+        Some(Transform {
+            origin: Point::new(-origin.x, -origin.y),
+            ..inverse
+        })
+    }
+}
+
 impl Default for Transform {
     fn default() -> Self {
         Self {
@@ -82,3 +643,216 @@ pub struct Spline {
     /// The points the path travels through
     pub points: Vec<SplineNode>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Point, b: Point) {
+        assert!(
+            (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let t = Transform {
+            origin: Point::new(3.0, -2.0),
+            basis_x: Point::new(2.0, 1.0),
+            basis_y: Point::new(-0.5, 3.0),
+        };
+        let inverse = t.inverse().unwrap();
+        for p in [
+            Point::new(0.0, 0.0),
+            Point::new(1.5, -4.0),
+            Point::new(-7.0, 2.0),
+        ] {
+            assert_close(inverse.apply(t.apply(p)), p);
+            assert_close(t.apply(inverse.apply(p)), p);
+        }
+    }
+
+    #[test]
+    fn inverse_of_identity_is_identity() {
+        let inverse = Transform::default().inverse().unwrap();
+        assert_close(inverse.apply(Point::new(2.0, 3.0)), Point::new(2.0, 3.0));
+    }
+
+    #[test]
+    fn flat_transforms_have_no_inverse() {
+        let flat = Transform {
+            origin: Point::default(),
+            basis_x: Point::new(1.0, 2.0),
+            basis_y: Point::new(2.0, 4.0),
+        };
+        assert!(flat.inverse().is_none());
+        let infinite = Transform {
+            basis_x: Point::new(f64::INFINITY, 0.0),
+            ..Default::default()
+        };
+        assert!(infinite.inverse().is_none());
+    }
+
+    fn rgba(s: &str) -> Option<(u8, u8, u8, u8)> {
+        let c = s.parse::<Rgba>().ok()?;
+        Some((c.red, c.green, c.blue, c.alpha))
+    }
+
+    fn color(s: &str) -> Result<String, RejectReason> {
+        let mut color = Color(s.to_string());
+        color.normalize()?;
+        Ok(color.as_str().to_string())
+    }
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(rgba("#f00"), Some((255, 0, 0, 255)));
+        assert_eq!(rgba("#f008"), Some((255, 0, 0, 0x88)));
+        assert_eq!(rgba("#12AbEf"), Some((0x12, 0xab, 0xef, 255)));
+        assert_eq!(rgba("#12abef80"), Some((0x12, 0xab, 0xef, 0x80)));
+    }
+
+    #[test]
+    fn parse_functions() {
+        assert_eq!(rgba("rgb(10, 20, 30)"), Some((10, 20, 30, 255)));
+        assert_eq!(rgba("rgba(10, 20, 30, 0)"), Some((10, 20, 30, 0)));
+        assert_eq!(rgba("hsl(120, 100%, 50%)"), Some((0, 255, 0, 255)));
+        assert_eq!(rgba("hsla(0, 100%, 50%, 0)"), Some((255, 0, 0, 0)));
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(rgba("black"), Some((0, 0, 0, 255)));
+        assert_eq!(rgba("CornflowerBlue"), Some((100, 149, 237, 255)));
+        assert_eq!(rgba("transparent"), Some((0, 0, 0, 0)));
+        assert_eq!(rgba("rebeccapurple"), Some((0x66, 0x33, 0x99, 255)));
+    }
+
+    #[test]
+    fn parse_rejects_invalid() {
+        for s in [
+            "",
+            "#12",
+            "#1234567",
+            "#ggg",
+            "notacolor",
+            "rgb(1, 2)",
+            "url(#x)",
+        ] {
+            assert_eq!(rgba(s), None, "{s:?} was accepted");
+        }
+        assert_eq!(rgba(&format!("#{}", "0".repeat(MAX_COLOR_LENGTH))), None);
+    }
+
+    #[test]
+    fn colors_are_normalized() {
+        assert_eq!(color("#F00").unwrap(), "#ff0000");
+        assert_eq!(color("rgba(255, 0, 0, 0)").unwrap(), "#ff000000");
+        assert_eq!(color("hsl(240, 100%, 50%)").unwrap(), "#0000ff");
+        assert_eq!(color("white").unwrap(), "#ffffff");
+        assert_eq!(color("transparent").unwrap(), "#00000000");
+        assert!(matches!(
+            color("notacolor"),
+            Err(RejectReason::IncorrectType { key: Some(key), .. }) if key == "color"
+        ));
+    }
+
+    #[test]
+    fn invalid_colors_are_still_received() {
+        let color: Color = serde_json::from_value(serde_json::json!("notacolor")).unwrap();
+        assert_eq!(color.as_str(), "notacolor");
+    }
+
+    #[test]
+    fn invalid_stored_colors_fall_back() {
+        let mut color = Color("notacolor".to_string());
+        color.normalize_or(Color::from_rgba(0, 0, 0, u8::MAX));
+        assert_eq!(color.as_str(), "#000000");
+
+        let mut color = Color("Red".to_string());
+        color.normalize_or(Color::from_rgba(0, 0, 0, u8::MAX));
+        assert_eq!(color.as_str(), "#ff0000");
+    }
+
+    fn stroke(width: f64, dash: Vec<f64>) -> Stroke {
+        Stroke {
+            width,
+            color: Color::from_rgba(0, 0, 0, u8::MAX),
+            dash,
+            cap: LineCap::default(),
+            join: LineJoin::default(),
+            opacity: 1.0,
+        }
+    }
+
+    #[test]
+    fn stroke_check_limits_width_and_dash() {
+        assert!(stroke(0.0, vec![]).check().is_ok());
+        assert!(stroke(2.0, vec![1.0, 0.5]).check().is_ok());
+        for width in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                stroke(width, vec![]).check().is_err(),
+                "{width} was accepted"
+            );
+        }
+        assert!(stroke(1.0, vec![-1.0]).check().is_err());
+        assert!(stroke(1.0, vec![1.0; MAX_DASH_LENGTHS + 1])
+            .check()
+            .is_err());
+    }
+
+    fn radial(radius: f64, offsets: &[f64]) -> Fill {
+        Fill::Gradient(Gradient::Radial {
+            center: Point::new(0.5, 0.5),
+            radius,
+            stops: offsets
+                .iter()
+                .map(|&offset| GradientStop {
+                    offset,
+                    color: Color::from_rgba(0, 0, 0, u8::MAX),
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn fill_check_accepts_valid_gradients() {
+        assert!(Fill::Solid(Color::from_rgba(0, 0, 0, u8::MAX))
+            .check()
+            .is_ok());
+        assert!(radial(0.5, &[0.0, 0.5, 1.0]).check().is_ok());
+    }
+
+    #[test]
+    fn fill_check_rejects_bad_gradients() {
+        assert!(radial(-1.0, &[0.0]).check().is_err());
+        assert!(radial(f64::NAN, &[0.0]).check().is_err());
+        assert!(radial(0.5, &[1.5]).check().is_err());
+        assert!(radial(0.5, &[-0.1]).check().is_err());
+        assert!(radial(0.5, &[f64::NAN]).check().is_err());
+        assert!(radial(0.5, &[0.5; MAX_GRADIENT_STOPS + 1]).check().is_err());
+    }
+
+    #[test]
+    fn text_style_check_limits_size_and_family() {
+        assert!(TextStyle::default().check().is_ok());
+        for font_size in [0.0, -1.0, f64::NAN, f64::INFINITY, MAX_FONT_SIZE * 2.0] {
+            let style = TextStyle {
+                font_size,
+                ..Default::default()
+            };
+            assert!(style.check().is_err(), "{font_size} was accepted");
+        }
+        let style = TextStyle {
+            font_family: "a".repeat(MAX_FONT_FAMILY_LENGTH + 1),
+            ..Default::default()
+        };
+        assert!(style.check().is_err());
+    }
+
+    #[test]
+    fn default_text_color_is_normalized() {
+        assert_eq!(TextStyle::default().color.as_str(), "#000000");
+    }
+}
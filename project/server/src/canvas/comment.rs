@@ -0,0 +1,63 @@
+//! Discussions attached to items or places on the board
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "codegen")]
+use ts_rs::TS;
+
+use crate::message::{ItemID, ThreadID};
+
+use super::Point;
+
+/// The longest comment accepted, in bytes
+pub const MAX_COMMENT_LENGTH: usize = 10_000;
+
+/// What a comment thread is attached to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum CommentAnchor {
+    /// An item, which the thread follows around the board
+    Item(ItemID),
+    /// A fixed place on the board
+    Position(Point),
+}
+
+/// A single message in a thread
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct Comment {
+    /// The name of the client who wrote the comment
+    pub author: String,
+    #[allow(missing_docs)]
+    pub text: String,
+    /// When the comment was written, in milliseconds since the Unix epoch
+    #[cfg_attr(feature = "codegen", ts(type = "number"))]
+    pub time: u64,
+}
+
+/// A discussion, starting with the comment that opened it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct CommentThread {
+    #[allow(missing_docs)]
+    pub id: ThreadID,
+    #[allow(missing_docs)]
+    pub anchor: CommentAnchor,
+    /// Every comment in the order they were written
+    pub comments: Vec<Comment>,
+    /// Whether the discussion has been settled
+    #[serde(default)]
+    pub resolved: bool,
+}
+
+impl Comment {
+    /// Create a comment written now
+    pub fn new(author: String, text: String) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("This code should not be running before the UNIX epoch")
+            .as_millis() as u64;
+        Self { author, text, time }
+    }
+}
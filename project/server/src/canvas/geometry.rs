@@ -0,0 +1,933 @@
+//! Extents of items, hit testing and simplification of curves
+//!
+//! Items are measured as they are drawn: shapes are the unit square or circle under their [`Transform`]
+//! with the stroke scaled along with them, and text is pixel-sized content centred on its transform.
+
+use std::collections::BTreeSet;
+
+use super::{
+    item::{
+        ConnectorItem, EllipseItem, FrameItem, ImageItem, LineItem, PathItem, PolygonItem,
+        RectangleItem, StickyNoteItem, TagItem, TextItem,
+    },
+    Item, Marker, Point, Rect, Spline, SplineNode, Stroke, TextAlign, TextAnchor, Transform,
+    FONT_SIZE, PX_PER_UNIT,
+};
+
+/// Number of points sampled along each segment of a [`Spline`]
+const SPLINE_SAMPLES: usize = 16;
+
+/// Approximate width of a character relative to the font size
+const CHAR_WIDTH: f64 = 0.55;
+
+/// Height of a line of text relative to the font size
+const LINE_HEIGHT: f64 = 1.2;
+
+/// Space left around the text on a sticky note, relative to the size of the note
+const NOTE_PADDING: f64 = 0.08;
+
+/// The largest font size (in pixels) sticky note text grows to
+const MAX_NOTE_FONT_SIZE: f64 = FONT_SIZE * 4.0;
+
+pub(super) fn sub(a: Point, b: Point) -> Point {
+    Point::new(a.x - b.x, a.y - b.y)
+}
+
+pub(super) fn dot(a: Point, b: Point) -> f64 {
+    a.x * b.x + a.y * b.y
+}
+
+pub(super) fn length(a: Point) -> f64 {
+    a.x.hypot(a.y)
+}
+
+/// The distance from `p` to the line segment between `a` and `b`
+pub(super) fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let ab = sub(b, a);
+    let len_sq = dot(ab, ab);
+    let t = if len_sq > 0.0 {
+        (dot(sub(p, a), ab) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    length(sub(p, Point::new(a.x + ab.x * t, a.y + ab.y * t)))
+}
+
+/// The distance from `p` to the nearest point on a series of line segments
+fn distance_to_polyline(p: Point, points: &[Point]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| distance_to_segment(p, pair[0], pair[1]))
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Mark the points to keep so that no point is further than `tolerance` from the lines between kept points
+///
+/// This is the Ramer-Douglas-Peucker algorithm, with a stack instead of recursion since paths can be long
+pub(super) fn douglas_peucker(points: &[Point], tolerance: f64) -> Vec<bool> {
+    let mut keep = vec![false; points.len()];
+    let Some(last) = points.len().checked_sub(1) else {
+        return keep;
+    };
+    keep[0] = true;
+    keep[last] = true;
+    let mut ranges = vec![(0, last)];
+    while let Some((start, end)) = ranges.pop() {
+        let furthest = (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[start], points[end]),
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = furthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+    keep
+}
+
+/// Even-odd test for whether a point is inside a closed polygon
+fn polygon_contains(points: &[Point], p: Point) -> bool {
+    let mut inside = false;
+    let mut prev = match points.last() {
+        Some(&last) => last,
+        None => return false,
+    };
+    for &current in points {
+        if (current.y > p.y) != (prev.y > p.y)
+            && p.x < (prev.x - current.x) * (p.y - current.y) / (prev.y - current.y) + current.x
+        {
+            inside = !inside;
+        }
+        prev = current;
+    }
+    inside
+}
+
+/// A box in an item's own space, from `-half` to `half` around `centre`
+fn local_box(centre: Point, half_width: f64, half_height: f64) -> Transform {
+    Transform {
+        origin: centre,
+        basis_x: Point::new(half_width * 2.0, 0.0),
+        basis_y: Point::new(0.0, half_height * 2.0),
+    }
+}
+
+/// The rectangle covered by the unit square under a transform
+fn unit_square_bounds(t: &Transform) -> Rect {
+    let half_x = (t.basis_x.x.abs() + t.basis_y.x.abs()) / 2.0;
+    let half_y = (t.basis_x.y.abs() + t.basis_y.y.abs()) / 2.0;
+    Rect {
+        min: Point::new(t.origin.x - half_x, t.origin.y - half_y),
+        max: Point::new(t.origin.x + half_x, t.origin.y + half_y),
+    }
+}
+
+/// The axis-aligned box as an oriented box
+fn rect_to_box(rect: Rect) -> Transform {
+    local_box(
+        Point::new(
+            (rect.min.x + rect.max.x) / 2.0,
+            (rect.min.y + rect.max.y) / 2.0,
+        ),
+        rect.width() / 2.0,
+        rect.height() / 2.0,
+    )
+}
+
+/// Whether a point in a box's own space is inside it, allowing `margin` in either direction
+fn in_unit_square(local: Point, margin_x: f64, margin_y: f64) -> bool {
+    local.x.abs() <= 0.5 + margin_x && local.y.abs() <= 0.5 + margin_y
+}
+
+/// How far `tolerance` on the board reaches along each axis of a transform's space
+fn local_margins(t: &Transform, tolerance: f64) -> (f64, f64) {
+    let margin = |v: Point| match length(v) {
+        0.0 => 0.0,
+        len => tolerance / len,
+    };
+    (margin(t.basis_x), margin(t.basis_y))
+}
+
+/// Test a point against the unit square under a transform
+fn box_contains(t: &Transform, p: Point, tolerance: f64) -> bool {
+    let Some(inverse) = t.inverse() else {
+        return false;
+    };
+    let (margin_x, margin_y) = local_margins(t, tolerance);
+    in_unit_square(inverse.apply(p), margin_x, margin_y)
+}
+
+impl Spline {
+    /// The cubic Bézier segments of the curve, as drawn by the client
+    ///
+    /// The first segment starts with no tangent, so the velocity of the first node has no effect
+    pub fn segments(&self) -> impl Iterator<Item = [Point; 4]> + '_ {
+        (1..self.points.len()).map(|i| self.segment(i - 1))
+    }
+
+    /// The segment from node `i` to the next, see [`Self::segments`]
+    fn segment(&self, i: usize) -> [Point; 4] {
+        let (from, to) = (&self.points[i], &self.points[i + 1]);
+        let c1 = if i == 0 {
+            from.position
+        } else {
+            Point::new(
+                from.position.x + from.velocity.x,
+                from.position.y + from.velocity.y,
+            )
+        };
+        let c2 = sub(to.position, to.velocity);
+        [from.position, c1, c2, to.position]
+    }
+
+    /// Remove as many nodes as possible while staying within `tolerance` of every original node,
+    /// fitting new velocities so the remaining nodes are joined smoothly
+    ///
+    /// Turns sharper than a right angle are kept as corners. A tolerance of zero leaves the spline unchanged
+    pub fn simplify(self, tolerance: f64) -> Spline {
+        if tolerance <= 0.0 || self.points.len() <= 2 {
+            return self;
+        }
+        let positions: Vec<_> = self.points.iter().map(|n| n.position).collect();
+
+        let keep = douglas_peucker(&positions, tolerance);
+        // The original index of each remaining node
+        let mut indices: Vec<_> = (0..keep.len()).filter(|&i| keep[i]).collect();
+        let mut spline = fit_spline(indices.iter().map(|&i| positions[i]).collect());
+
+        // The fitted curves bulge away from the straight lines tested above, so split any which stray too far.
+        // Splitting only changes the nearby curves, so only those are checked again
+        let mut unchecked: BTreeSet<usize> = (0..indices.len() - 1).collect();
+        while !unchecked.is_empty() {
+            let mut splits = Vec::new();
+            for &segment in &unchecked {
+                let curve = Spline::sample_segment(spline.segment(segment));
+                let furthest = (indices[segment] + 1..indices[segment + 1])
+                    .map(|i| (i, distance_to_polyline(positions[i], &curve)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, distance)) = furthest {
+                    if distance > tolerance {
+                        splits.push((segment, i));
+                    }
+                }
+            }
+            unchecked.clear();
+
+            // Each split shifts the nodes after it along by one
+            let mut refit = BTreeSet::new();
+            for (shift, &(segment, i)) in splits.iter().enumerate() {
+                let node = segment + shift + 1;
+                indices.insert(node, i);
+                spline.points.insert(
+                    node,
+                    SplineNode {
+                        position: positions[i],
+                        velocity: Point::default(),
+                    },
+                );
+                // A node's velocity depends on its neighbours
+                refit.extend([node - 1, node, node + 1]);
+            }
+
+            let last = spline.points.len() - 1;
+            for &node in &refit {
+                spline.points[node].velocity = fit_velocity(
+                    node.checked_sub(1).map(|j| spline.points[j].position),
+                    spline.points[node].position,
+                    spline.points.get(node + 1).map(|n| n.position),
+                );
+                // Both segments either side of the node depend on its velocity
+                if node > 0 {
+                    unchecked.insert(node - 1);
+                }
+                if node < last {
+                    unchecked.insert(node);
+                }
+            }
+        }
+        spline
+    }
+
+    /// Points along a single cubic Bézier segment, including both ends
+    fn sample_segment([p0, p1, p2, p3]: [Point; 4]) -> Vec<Point> {
+        (0..=SPLINE_SAMPLES)
+            .map(|step| {
+                let t = step as f64 / SPLINE_SAMPLES as f64;
+                let u = 1.0 - t;
+                let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                Point::new(
+                    a * p0.x + b * p1.x + c * p2.x + d * p3.x,
+                    a * p0.y + b * p1.y + c * p2.y + d * p3.y,
+                )
+            })
+            .collect()
+    }
+
+    /// Points along the curve, close enough together to be treated as straight lines
+    pub fn sample(&self) -> Vec<Point> {
+        let mut points: Vec<_> = self
+            .points
+            .first()
+            .map(|n| n.position)
+            .into_iter()
+            .collect();
+        for segment in self.segments() {
+            points.extend(Spline::sample_segment(segment).into_iter().skip(1));
+        }
+        points
+    }
+
+    /// The ends of the curve, each with a point that leads up to it
+    ///
+    /// Returns `(from, tip)` for the start and then the end, as used to place [`Marker`]s,
+    /// or [`None`] if the curve doesn't go anywhere
+    pub fn ends(&self) -> Option<[(Point, Point); 2]> {
+        let segments: Vec<_> = self.segments().collect();
+        let start = segments.first()?[0];
+        let end = segments.last()?[3];
+        let differs = |tip: Point| move |p: &&Point| p.x != tip.x || p.y != tip.y;
+        let after_start = segments.iter().flat_map(|s| &s[1..]).find(differs(start))?;
+        let before_end = segments
+            .iter()
+            .rev()
+            .flat_map(|s| s[..3].iter().rev())
+            .find(differs(end))?;
+        Some([(*after_start, start), (*before_end, end)])
+    }
+}
+
+/// The corners of the square a marker is drawn in, see [`Marker`]
+fn marker_corners(marker: Marker, from: Point, tip: Point, width: f64) -> [Point; 4] {
+    let size = marker.size(width);
+    let direction = sub(tip, from);
+    let (along, across) = match length(direction) {
+        0.0 => (Point::default(), Point::default()),
+        len => (
+            Point::new(direction.x / len * size, direction.y / len * size),
+            Point::new(
+                -direction.y / len * size / 2.0,
+                direction.x / len * size / 2.0,
+            ),
+        ),
+    };
+    let base = sub(tip, along);
+    [
+        Point::new(tip.x + across.x, tip.y + across.y),
+        sub(tip, across),
+        Point::new(base.x + across.x, base.y + across.y),
+        sub(base, across),
+    ]
+}
+
+/// The outline of the square a marker is drawn in, in order around its edge
+fn marker_outline(marker: Marker, from: Point, tip: Point, width: f64) -> Option<[Point; 4]> {
+    if marker == Marker::None {
+        return None;
+    }
+    let [tip_a, tip_b, base_a, base_b] = marker_corners(marker, from, tip, width);
+    Some([tip_a, tip_b, base_b, base_a])
+}
+
+/// Whether `p` is inside a closed outline, or within `tolerance` of its edge
+fn outline_contains(outline: &[Point], p: Point, tolerance: f64) -> bool {
+    polygon_contains(outline, p)
+        || outline
+            .iter()
+            .zip(outline.iter().cycle().skip(1))
+            .any(|(a, b)| distance_to_segment(p, *a, *b) <= tolerance)
+}
+
+/// The points a path and its markers are drawn around, in the path's own space
+fn path_outline(path: &PathItem) -> Vec<Point> {
+    let mut points = path.path.sample();
+    if let Some([start, end]) = path.path.ends() {
+        for (marker, (from, tip)) in [(path.start_marker, start), (path.end_marker, end)] {
+            if marker != Marker::None {
+                points.extend(marker_corners(marker, from, tip, path.stroke.width));
+            }
+        }
+    }
+    points
+}
+
+/// The thickness of a line including the markers at its ends
+fn marked_width(stroke: &Stroke, start_marker: Marker, end_marker: Marker) -> f64 {
+    stroke
+        .width
+        .max(start_marker.size(stroke.width))
+        .max(end_marker.size(stroke.width))
+}
+
+/// A smooth spline through every position
+fn fit_spline(positions: Vec<Point>) -> Spline {
+    let points = (0..positions.len())
+        .map(|i| SplineNode {
+            position: positions[i],
+            velocity: fit_velocity(
+                i.checked_sub(1).map(|j| positions[j]),
+                positions[i],
+                positions.get(i + 1).copied(),
+            ),
+        })
+        .collect();
+    Spline { points }
+}
+
+/// A velocity for a node which curves smoothly between its neighbours
+///
+/// The control points are placed a third of the way along the shorter neighbouring segment,
+/// so the curve does not overshoot either neighbour
+fn fit_velocity(prev: Option<Point>, current: Point, next: Option<Point>) -> Point {
+    let unit = |v: Point| match length(v) {
+        0.0 => None,
+        len => Some((Point::new(v.x / len, v.y / len), len)),
+    };
+    let incoming = prev.and_then(|p| unit(sub(current, p)));
+    let outgoing = next.and_then(|n| unit(sub(n, current)));
+    let (direction, reach) = match (incoming, outgoing) {
+        (Some((a, len_a)), Some((b, len_b))) => {
+            if dot(a, b) < 0.0 {
+                return Point::default();
+            }
+            let Some((direction, _)) = unit(Point::new(a.x + b.x, a.y + b.y)) else {
+                return Point::default();
+            };
+            (direction, len_a.min(len_b))
+        }
+        (Some(only), None) | (None, Some(only)) => only,
+        (None, None) => return Point::default(),
+    };
+    let reach = reach / 3.0;
+    Point::new(direction.x * reach, direction.y * reach)
+}
+
+/// The box around a straight line of the given thickness
+fn line_box(start: Point, end: Point, width: f64) -> Transform {
+    let direction = sub(end, start);
+    let normal = match length(direction) {
+        0.0 => Point::new(0.0, 1.0),
+        len => Point::new(-direction.y / len, direction.x / len),
+    };
+    Transform {
+        origin: Point::new((start.x + end.x) / 2.0, (start.y + end.y) / 2.0),
+        basis_x: direction,
+        basis_y: Point::new(normal.x * width, normal.y * width),
+    }
+}
+
+/// Where a line from the middle of an item towards `toward` crosses its outline
+///
+/// The middle of the item is used if `toward` is inside it
+fn outline_crossing(item: &Item, toward: Point) -> Point {
+    let bounds = item.oriented_bounds();
+    let Some(inverse) = bounds.inverse() else {
+        return bounds.origin;
+    };
+    let local = inverse.apply(toward);
+    let reach = match item {
+        Item::Ellipse(_) => length(local),
+        _ => local.x.abs().max(local.y.abs()),
+    } * 2.0;
+    if reach <= 1.0 {
+        return bounds.origin;
+    }
+    bounds.apply(Point::new(local.x / reach, local.y / reach))
+}
+
+impl StickyNoteItem {
+    /// The font size (in pixels) the text is drawn at, so that it fits inside the note
+    pub fn font_size(&self) -> f64 {
+        let inner = 1.0 - NOTE_PADDING * 2.0;
+        let width = length(self.transform.basis_x) * PX_PER_UNIT * inner;
+        let height = length(self.transform.basis_y) * PX_PER_UNIT * inner;
+        let (text_width, text_height) = text_size(self.text.split('\n'));
+        let fit = (width / text_width).min(height / text_height) * FONT_SIZE;
+        fit.min(self.font_size_hint.unwrap_or(MAX_NOTE_FONT_SIZE))
+    }
+}
+
+impl ConnectorItem {
+    /// Move the ends of the connector onto the items it is attached to
+    pub fn attach(&mut self, source: &Item, target: &Item) {
+        let anchored = |item: &Item, anchor: Option<Point>| {
+            anchor.map(|anchor| item.oriented_bounds().apply(anchor))
+        };
+        let source_anchor = anchored(source, self.source_anchor);
+        let target_anchor = anchored(target, self.target_anchor);
+        self.start = source_anchor.unwrap_or_else(|| {
+            let toward = target_anchor.unwrap_or(target.oriented_bounds().origin);
+            outline_crossing(source, toward)
+        });
+        self.end = target_anchor.unwrap_or_else(|| {
+            let toward = source_anchor.unwrap_or(source.oriented_bounds().origin);
+            outline_crossing(target, toward)
+        });
+    }
+}
+
+/// The size of some lines of text in pixels
+fn text_size<'a>(lines: impl IntoIterator<Item = &'a str>) -> (f64, f64) {
+    let (count, longest) = lines.into_iter().fold((0, 0), |(count, longest), line| {
+        (count + 1, longest.max(line.chars().count()))
+    });
+    (
+        longest as f64 * CHAR_WIDTH * FONT_SIZE,
+        count.max(1) as f64 * LINE_HEIGHT * FONT_SIZE,
+    )
+}
+
+/// The box of pixel-sized content centred on a transform
+fn pixel_box(t: &Transform, (width, height): (f64, f64)) -> Transform {
+    t.compose(&local_box(
+        Point::default(),
+        width / PX_PER_UNIT / 2.0,
+        height / PX_PER_UNIT / 2.0,
+    ))
+}
+
+impl ImageItem {
+    /// The size the image is drawn at in pixels, if it is known
+    pub fn pixel_size(&self) -> Option<(f64, f64)> {
+        match (&self.crop, &self.info) {
+            (Some(crop), _) => Some((crop.width(), crop.height())),
+            (None, Some(info)) => Some((info.width as f64, info.height as f64)),
+            (None, None) => None,
+        }
+    }
+}
+
+impl FrameItem {
+    /// Whether an item lies entirely inside the frame
+    pub fn contains(&self, item: &Item) -> bool {
+        let Some(inverse) = self.transform.inverse() else {
+            return false;
+        };
+        let t = item.oriented_bounds();
+        [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]
+            .into_iter()
+            .all(|(x, y)| in_unit_square(inverse.apply(t.apply(Point::new(x, y))), 0.0, 0.0))
+    }
+}
+
+impl Item {
+    /// The smallest axis-aligned rectangle containing everything drawn for the item
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Item::Ellipse(EllipseItem {
+                transform, stroke, ..
+            }) => {
+                let radius = 0.5 + stroke.width / 2.0;
+                let half_x = radius * transform.basis_x.x.hypot(transform.basis_y.x);
+                let half_y = radius * transform.basis_x.y.hypot(transform.basis_y.y);
+                Rect {
+                    min: Point::new(transform.origin.x - half_x, transform.origin.y - half_y),
+                    max: Point::new(transform.origin.x + half_x, transform.origin.y + half_y),
+                }
+            }
+            Item::Polygon(PolygonItem { points, stroke, .. }) => {
+                Rect::from_points(points.iter().copied())
+                    .unwrap_or(Rect::from_point(Point::default()))
+                    .expand(stroke.width / 2.0)
+            }
+            Item::Path(path) => {
+                let PathItem {
+                    transform, stroke, ..
+                } = path;
+                let width = stroke.width * transform.scale_factor();
+                Rect::from_points(path_outline(path).into_iter().map(|p| transform.apply(p)))
+                    .unwrap_or(Rect::from_point(transform.origin))
+                    .expand(width / 2.0)
+            }
+            _ => unit_square_bounds(&self.oriented_bounds()),
+        }
+    }
+
+    /// A box containing the item, aligned with the item where possible
+    ///
+    /// The box is the unit square centred on the origin under the returned [`Transform`],
+    /// the same way [`RectangleItem`]s are positioned
+    pub fn oriented_bounds(&self) -> Transform {
+        match self {
+            Item::Rectangle(RectangleItem {
+                transform, stroke, ..
+            })
+            | Item::Ellipse(EllipseItem {
+                transform, stroke, ..
+            }) => {
+                let half = 0.5 + stroke.width / 2.0;
+                transform.compose(&local_box(Point::default(), half, half))
+            }
+            Item::Line(LineItem {
+                start,
+                end,
+                stroke,
+                start_marker,
+                end_marker,
+            })
+            | Item::Connector(ConnectorItem {
+                start,
+                end,
+                stroke,
+                start_marker,
+                end_marker,
+                ..
+            }) => line_box(
+                *start,
+                *end,
+                marked_width(stroke, *start_marker, *end_marker),
+            ),
+            Item::Polygon(_) => rect_to_box(self.bounds()),
+            Item::Path(path) => {
+                let local = Rect::from_points(path_outline(path))
+                    .unwrap_or(Rect::from_point(Point::default()))
+                    .expand(path.stroke.width / 2.0);
+                path.transform.compose(&rect_to_box(local))
+            }
+            Item::Image(image) => match image.pixel_size() {
+                // Drawn from its top-left corner at its size in pixels
+                Some((width, height)) => {
+                    let (half_width, half_height) =
+                        (width / PX_PER_UNIT / 2.0, height / PX_PER_UNIT / 2.0);
+                    image.transform.compose(&local_box(
+                        Point::new(half_width, half_height),
+                        half_width,
+                        half_height,
+                    ))
+                }
+                // Images the server hasn't inspected have no known size, so the transform is the best estimate
+                None => image.transform.clone(),
+            },
+            Item::StickyNote(StickyNoteItem { transform, .. })
+            | Item::Frame(FrameItem { transform, .. }) => transform.clone(),
+            Item::Text(TextItem {
+                transform,
+                text,
+                style,
+            }) => {
+                let scale = style.font_size / FONT_SIZE;
+                let (width, height) = text_size(text.split('\n'));
+                let (width, height) = (width * scale, height * scale);
+                let x = match style.align {
+                    TextAlign::Left => width / 2.0,
+                    TextAlign::Center => 0.0,
+                    TextAlign::Right => -width / 2.0,
+                };
+                let y = match style.anchor {
+                    TextAnchor::Top => height / 2.0,
+                    TextAnchor::Middle => 0.0,
+                    TextAnchor::Bottom => -height / 2.0,
+                };
+                let centre = Point::new(x / PX_PER_UNIT, y / PX_PER_UNIT);
+                transform.compose(&local_box(
+                    centre,
+                    width / PX_PER_UNIT / 2.0,
+                    height / PX_PER_UNIT / 2.0,
+                ))
+            }
+            Item::Link(link) => pixel_box(&link.transform, text_size([link.label()])),
+            Item::Tag(TagItem {
+                transform, data, ..
+            }) => pixel_box(transform, text_size([data.as_str()])),
+            Item::Table(table) => {
+                let half = table.stroke.width / 2.0;
+                let (width, height) = (table.width() / 2.0, table.height() / 2.0);
+                table.transform.compose(&local_box(
+                    Point::new(width, height),
+                    width + half,
+                    height + half,
+                ))
+            }
+        }
+    }
+
+    /// Whether a point is on the item, or within `tolerance` of it
+    pub fn contains_point(&self, p: Point, tolerance: f64) -> bool {
+        if !self.bounds().expand(tolerance).contains(p) {
+            return false;
+        }
+        match self {
+            Item::Ellipse(EllipseItem {
+                transform, stroke, ..
+            }) => {
+                let Some(inverse) = transform.inverse() else {
+                    return false;
+                };
+                let local = inverse.apply(p);
+                let (margin_x, margin_y) = local_margins(transform, tolerance);
+                let radius_x = 0.5 + stroke.width / 2.0 + margin_x;
+                let radius_y = 0.5 + stroke.width / 2.0 + margin_y;
+                (local.x / radius_x).powi(2) + (local.y / radius_y).powi(2) <= 1.0
+            }
+            Item::Line(LineItem {
+                start,
+                end,
+                stroke,
+                start_marker,
+                end_marker,
+            })
+            | Item::Connector(ConnectorItem {
+                start,
+                end,
+                stroke,
+                start_marker,
+                end_marker,
+                ..
+            }) => {
+                let ends = [(*start_marker, *end, *start), (*end_marker, *start, *end)];
+                distance_to_segment(p, *start, *end) <= stroke.width / 2.0 + tolerance
+                    || ends.into_iter().any(|(marker, from, tip)| {
+                        marker_outline(marker, from, tip, stroke.width)
+                            .is_some_and(|outline| outline_contains(&outline, p, tolerance))
+                    })
+            }
+            Item::Polygon(PolygonItem { points, stroke, .. }) => {
+                outline_contains(points, p, stroke.width / 2.0 + tolerance)
+            }
+            Item::Path(path_item) => {
+                let PathItem {
+                    transform,
+                    path,
+                    stroke,
+                    start_marker,
+                    end_marker,
+                } = path_item;
+                let reach = stroke.width * transform.scale_factor() / 2.0 + tolerance;
+                let points: Vec<_> = path
+                    .sample()
+                    .into_iter()
+                    .map(|p| transform.apply(p))
+                    .collect();
+                let on_path = match points[..] {
+                    [only] => length(sub(p, only)) <= reach,
+                    _ => points
+                        .windows(2)
+                        .any(|pair| distance_to_segment(p, pair[0], pair[1]) <= reach),
+                };
+                let on_marker = || {
+                    let Some([start, end]) = path.ends() else {
+                        return false;
+                    };
+                    [(*start_marker, start), (*end_marker, end)]
+                        .into_iter()
+                        .filter_map(|(marker, (from, tip))| {
+                            marker_outline(marker, from, tip, stroke.width)
+                        })
+                        .any(|outline| {
+                            let outline = outline.map(|corner| transform.apply(corner));
+                            outline_contains(&outline, p, tolerance)
+                        })
+                };
+                on_path || on_marker()
+            }
+            _ => box_contains(&self.oriented_bounds(), p, tolerance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(value: serde_json::Value) -> Item {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn stroke() -> serde_json::Value {
+        json!({ "width": 0.1, "color": "#000000ff" })
+    }
+
+    fn spline(positions: &[Point]) -> Spline {
+        Spline {
+            points: positions
+                .iter()
+                .map(|&position| SplineNode {
+                    position,
+                    velocity: Point::default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// A wobbly stroke, sampled densely like a drawn path
+    fn wave() -> Vec<Point> {
+        (0..400)
+            .map(|i| {
+                let x = i as f64 * 0.025;
+                Point::new(x, (x * 1.7).sin() + (x * 5.3).sin() * 0.2)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn douglas_peucker_drops_collinear_points() {
+        let points: Vec<_> = (0..10)
+            .map(|i| Point::new(i as f64, i as f64 * 0.5))
+            .collect();
+        let keep = douglas_peucker(&points, 0.01);
+        let kept: Vec<_> = (0..keep.len()).filter(|&i| keep[i]).collect();
+        assert_eq!(kept, [0, 9]);
+    }
+
+    #[test]
+    fn douglas_peucker_keeps_corners() {
+        let points = [
+            Point::new(0.0, 0.0),
+            Point::new(1.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 1.0),
+            Point::new(2.0, 2.0),
+        ];
+        assert_eq!(
+            douglas_peucker(&points, 0.1),
+            [true, false, true, false, true]
+        );
+        // Everything is within a large tolerance of the line between the ends
+        assert_eq!(
+            douglas_peucker(&points, 2.0),
+            [true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn douglas_peucker_short_input() {
+        assert!(douglas_peucker(&[], 0.1).is_empty());
+        assert_eq!(douglas_peucker(&[Point::new(1.0, 1.0)], 0.1), [true]);
+    }
+
+    #[test]
+    fn simplify_zero_tolerance_is_unchanged() {
+        let points = wave();
+        assert_eq!(spline(&points).simplify(0.0).points.len(), points.len());
+    }
+
+    #[test]
+    fn simplify_stays_within_tolerance() {
+        let points = wave();
+        let tolerance = 0.025;
+        let simplified = spline(&points).simplify(tolerance);
+        assert!(simplified.points.len() < points.len() / 4);
+
+        let first = simplified.points.first().unwrap().position;
+        let last = simplified.points.last().unwrap().position;
+        assert_eq!((first.x, first.y), (points[0].x, points[0].y));
+        assert_eq!((last.x, last.y), (points[399].x, points[399].y));
+
+        let curve = simplified.sample();
+        for p in points {
+            // Sampling the curve cuts corners slightly
+            assert!(distance_to_polyline(p, &curve) <= tolerance * 1.1);
+        }
+    }
+
+    #[test]
+    fn simplify_matches_refitting_everything() {
+        let points = wave();
+        let tolerance = 0.01;
+
+        // The straightforward version, which refits the whole spline after every round of splits
+        let mut keep = douglas_peucker(&points, tolerance);
+        let expected = loop {
+            let indices: Vec<_> = (0..keep.len()).filter(|&i| keep[i]).collect();
+            let fitted = fit_spline(indices.iter().map(|&i| points[i]).collect());
+            let mut changed = false;
+            for (segment, range) in fitted.segments().zip(indices.windows(2)) {
+                let curve = Spline::sample_segment(segment);
+                let furthest = (range[0] + 1..range[1])
+                    .map(|i| (i, distance_to_polyline(points[i], &curve)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, distance)) = furthest {
+                    if distance > tolerance {
+                        keep[i] = true;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break fitted;
+            }
+        };
+
+        let simplified = spline(&points).simplify(tolerance);
+        assert_eq!(simplified.points.len(), expected.points.len());
+        for (a, b) in simplified.points.iter().zip(&expected.points) {
+            assert_eq!((a.position.x, a.position.y), (b.position.x, b.position.y));
+            assert_eq!((a.velocity.x, a.velocity.y), (b.velocity.x, b.velocity.y));
+        }
+    }
+
+    #[test]
+    fn rectangle_contains_inside_and_stroke() {
+        let rect = item(json!({
+            "type": "Rectangle",
+            "transform": { "origin": { "x": 5.0, "y": 5.0 }, "basisX": { "x": 2.0, "y": 0.0 }, "basisY": { "x": 0.0, "y": 2.0 } },
+            "stroke": stroke(),
+            "fill": "#ffffffff",
+        }));
+        assert!(rect.contains_point(Point::new(5.0, 5.0), 0.0));
+        assert!(rect.contains_point(Point::new(6.04, 5.0), 0.0));
+        assert!(!rect.contains_point(Point::new(6.2, 5.0), 0.0));
+        assert!(rect.contains_point(Point::new(6.2, 5.0), 0.2));
+    }
+
+    #[test]
+    fn ellipse_excludes_corners() {
+        let ellipse = item(json!({
+            "type": "Ellipse",
+            "transform": { "origin": { "x": 0.0, "y": 0.0 }, "basisX": { "x": 2.0, "y": 0.0 }, "basisY": { "x": 0.0, "y": 2.0 } },
+            "stroke": stroke(),
+            "fill": "#ffffffff",
+        }));
+        assert!(ellipse.contains_point(Point::new(0.9, 0.0), 0.0));
+        assert!(!ellipse.contains_point(Point::new(0.95, 0.95), 0.0));
+    }
+
+    #[test]
+    fn line_markers_are_hit() {
+        let line = item(json!({
+            "type": "Line",
+            "start": { "x": 0.0, "y": 0.0 },
+            "end": { "x": 10.0, "y": 0.0 },
+            "stroke": stroke(),
+            "endMarker": "Arrow",
+        }));
+        // The arrow is 0.4 wide, much wider than the line
+        assert!(line.contains_point(Point::new(9.8, 0.15), 0.0));
+        assert!(!line.contains_point(Point::new(9.8, 0.3), 0.0));
+        assert!(!line.contains_point(Point::new(0.2, 0.15), 0.0));
+        assert!(!line.contains_point(Point::new(5.0, 0.15), 0.0));
+    }
+
+    #[test]
+    fn path_markers_are_hit() {
+        let node = |x: f64| SplineNode {
+            position: Point::new(x, 0.0),
+            velocity: Point::new(1.0, 0.0),
+        };
+        let path = PathItem {
+            transform: Transform {
+                origin: Point::new(1.0, 1.0),
+                ..Default::default()
+            },
+            path: Spline {
+                points: vec![node(0.0), node(4.0)],
+            },
+            stroke: serde_json::from_value(stroke()).unwrap(),
+            start_marker: Marker::Circle,
+            end_marker: Marker::None,
+        }
+        .to_item();
+        assert!(path.contains_point(Point::new(3.0, 1.0), 0.0));
+        // The circle at the start is 0.3 across
+        assert!(path.contains_point(Point::new(1.1, 1.12), 0.0));
+        assert!(!path.contains_point(Point::new(4.9, 1.12), 0.0));
+    }
+}
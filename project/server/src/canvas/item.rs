@@ -1,9 +1,11 @@
 //! The item types themselves
 
-use super::{Color, Point, Spline, Stroke, Transform};
+use super::{Color, Fill, Marker, Point, Rect, Spline, Stroke, TextStyle, Transform};
 use crate::{
-    message::{reject::RejectReason, ItemID, LocationUpdate},
+    message::{reject::RejectReason, BoardID, ItemID, LocationUpdate},
     tags::TagID,
+    upload::MediaInfo,
+    utils::merge_patch,
 };
 use paste::paste;
 use serde::{Deserialize, Serialize};
@@ -22,6 +24,17 @@ macro_rules! item_enum {
             )*
         }
 
+        impl $enum_name {
+            /// The name of the item's type
+            pub fn type_name(&self) -> &'static str {
+                match self {
+                    $(
+                        Self::$name(_) => stringify!($name),
+                    )*
+                }
+            }
+        }
+
         $(
             impl paste!([<$name Item>]) {
                 /// Wraps self in the Item enum
@@ -49,11 +62,107 @@ item_enum! {
         Image,
         Text,
         Link,
-        Tag
+        Tag,
+        Connector,
+        StickyNote,
+        Table,
+        Frame
     }
 }
 
 impl Item {
+    /// Create a copy of the item with a JSON merge patch applied, failing if the result is not the same type of item
+    pub fn patched(&self, id: ItemID, patch: &serde_json::Value) -> Result<Item, RejectReason> {
+        let mut value = serde_json::to_value(self).expect("Items should always serialize");
+        merge_patch(&mut value, patch);
+
+        let incorrect_type = |received: String| RejectReason::IncorrectType {
+            key: Some(id.to_string()),
+            expected: self.type_name(),
+            received,
+        };
+
+        let item: Item =
+            serde_json::from_value(value).map_err(|e| incorrect_type(e.to_string()))?;
+        if std::mem::discriminant(self) == std::mem::discriminant(&item) {
+            Ok(item)
+        } else {
+            Err(incorrect_type(item.type_name().to_string()))
+        }
+    }
+
+    /// Every color used by the item
+    fn colors_mut(&mut self) -> Vec<&mut Color> {
+        match self {
+            Self::Rectangle(RectangleItem { stroke, fill, .. })
+            | Self::Ellipse(EllipseItem { stroke, fill, .. })
+            | Self::Polygon(PolygonItem { stroke, fill, .. }) => {
+                let mut colors = fill.colors_mut();
+                colors.push(&mut stroke.color);
+                colors
+            }
+            Self::Line(LineItem { stroke, .. })
+            | Self::Path(PathItem { stroke, .. })
+            | Self::Connector(ConnectorItem { stroke, .. })
+            | Self::Table(TableItem { stroke, .. }) => vec![&mut stroke.color],
+            Self::Text(TextItem { style, .. }) => vec![&mut style.color],
+            Self::StickyNote(StickyNoteItem { background, .. }) => vec![background],
+            Self::Image(_) | Self::Link(_) | Self::Tag(_) | Self::Frame(_) => Vec::new(),
+        }
+    }
+
+    /// Check that every color in the item is valid, rewriting them with [`Color::normalize`]
+    pub fn normalize_colors(&mut self) -> Result<(), RejectReason> {
+        self.colors_mut()
+            .into_iter()
+            .try_for_each(|color| color.normalize())
+    }
+
+    /// Rewrite every color in an item loaded from a file, replacing any that aren't valid with black
+    pub fn repair_colors(&mut self) {
+        for color in self.colors_mut() {
+            color.normalize_or(Color::from_rgba(0, 0, 0, u8::MAX));
+        }
+    }
+
+    /// The line the item is drawn with, if it has one
+    pub fn stroke(&self) -> Option<&Stroke> {
+        match self {
+            Self::Rectangle(RectangleItem { stroke, .. })
+            | Self::Ellipse(EllipseItem { stroke, .. })
+            | Self::Polygon(PolygonItem { stroke, .. })
+            | Self::Line(LineItem { stroke, .. })
+            | Self::Path(PathItem { stroke, .. })
+            | Self::Connector(ConnectorItem { stroke, .. })
+            | Self::Table(TableItem { stroke, .. }) => Some(stroke),
+            Self::Text(_)
+            | Self::StickyNote(_)
+            | Self::Image(_)
+            | Self::Link(_)
+            | Self::Tag(_)
+            | Self::Frame(_) => None,
+        }
+    }
+
+    /// How the inside of the item is painted, if it is a filled shape
+    pub fn fill(&self) -> Option<&Fill> {
+        match self {
+            Self::Rectangle(RectangleItem { fill, .. })
+            | Self::Ellipse(EllipseItem { fill, .. })
+            | Self::Polygon(PolygonItem { fill, .. }) => Some(fill),
+            Self::Line(_)
+            | Self::Path(_)
+            | Self::Connector(_)
+            | Self::Table(_)
+            | Self::Text(_)
+            | Self::StickyNote(_)
+            | Self::Image(_)
+            | Self::Link(_)
+            | Self::Tag(_)
+            | Self::Frame(_) => None,
+        }
+    }
+
     /// Attempt to update the position of an item, returning the original location if the update is invalid
     pub fn apply_location_update(
         &mut self,
@@ -81,7 +190,7 @@ impl Item {
         }
 
         let t = transform_types! {
-            Rectangle, Ellipse, Path, Image, Text, Link, Tag (item) => {
+            Rectangle, Ellipse, Path, Image, Text, Link, Tag, StickyNote, Table, Frame (item) => {
                 if let LocationUpdate::Transform(t) = update {
                     item.transform = t.clone();
                     Ok(())
@@ -108,6 +217,23 @@ impl Item {
                     "Transform"
                 ))}
             },
+            Connector(item) => {
+                if let LocationUpdate::Points(p) = update {
+                    if p.len() == 2 {
+                        item.start = p[0];
+                        item.end = p[1];
+                        Ok(())
+                    } else { Err((
+                        LocationUpdate::Points(vec![item.start, item.end]),
+                        "Point[2]",
+                        "Point[]"
+                    ))}
+                } else { Err((
+                    LocationUpdate::Points(vec![item.start, item.end]),
+                    "Point[2]",
+                    "Transform"
+                ))}
+            },
             Polygon(item) => {
                 if let LocationUpdate::Points(p) = update {
                     item.points = p.clone();
@@ -131,6 +257,55 @@ impl Item {
             )
         })
     }
+
+    /// The current position of the item, in the form [`Self::apply_location_update`] expects
+    pub fn location(&self) -> LocationUpdate {
+        match self {
+            Self::Rectangle(RectangleItem { transform, .. })
+            | Self::Ellipse(EllipseItem { transform, .. })
+            | Self::Path(PathItem { transform, .. })
+            | Self::Image(ImageItem { transform, .. })
+            | Self::Text(TextItem { transform, .. })
+            | Self::Link(LinkItem { transform, .. })
+            | Self::Tag(TagItem { transform, .. })
+            | Self::StickyNote(StickyNoteItem { transform, .. })
+            | Self::Table(TableItem { transform, .. })
+            | Self::Frame(FrameItem { transform, .. }) => {
+                LocationUpdate::Transform(transform.clone())
+            }
+            Self::Line(LineItem { start, end, .. })
+            | Self::Connector(ConnectorItem { start, end, .. }) => {
+                LocationUpdate::Points(vec![*start, *end])
+            }
+            Self::Polygon(PolygonItem { points, .. }) => LocationUpdate::Points(points.clone()),
+        }
+    }
+
+    /// Move the item as if it was drawn under `t`, the way a selection moves its items
+    pub fn transform_by(&mut self, t: &Transform) {
+        match self {
+            Self::Rectangle(RectangleItem { transform, .. })
+            | Self::Ellipse(EllipseItem { transform, .. })
+            | Self::Path(PathItem { transform, .. })
+            | Self::Image(ImageItem { transform, .. })
+            | Self::Text(TextItem { transform, .. })
+            | Self::Link(LinkItem { transform, .. })
+            | Self::Tag(TagItem { transform, .. })
+            | Self::StickyNote(StickyNoteItem { transform, .. })
+            | Self::Table(TableItem { transform, .. })
+            | Self::Frame(FrameItem { transform, .. }) => *transform = t.compose(transform),
+            Self::Line(LineItem { start, end, .. })
+            | Self::Connector(ConnectorItem { start, end, .. }) => {
+                *start = t.apply(*start);
+                *end = t.apply(*end);
+            }
+            Self::Polygon(PolygonItem { points, .. }) => {
+                for p in points {
+                    *p = t.apply(*p);
+                }
+            }
+        }
+    }
 }
 
 /// A rectangle.
@@ -142,7 +317,7 @@ impl Item {
 pub struct RectangleItem {
     pub transform: Transform,
     pub stroke: Stroke,
-    pub fill: Color,
+    pub fill: Fill,
 }
 
 /// An ellipse
@@ -154,17 +329,24 @@ pub struct RectangleItem {
 pub struct EllipseItem {
     pub transform: Transform,
     pub stroke: Stroke,
-    pub fill: Color,
+    pub fill: Fill,
 }
 
 /// A line segment between two points
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
 #[allow(missing_docs)]
 pub struct LineItem {
     pub start: Point,
     pub end: Point,
     pub stroke: Stroke,
+    /// The decoration at the start of the line
+    #[serde(default)]
+    pub start_marker: Marker,
+    /// The decoration at the end of the line
+    #[serde(default)]
+    pub end_marker: Marker,
 }
 
 /// A closed loop of points
@@ -174,17 +356,24 @@ pub struct LineItem {
 pub struct PolygonItem {
     pub points: Vec<Point>,
     pub stroke: Stroke,
-    pub fill: Color,
+    pub fill: Fill,
 }
 
 /// A hand-drawn path between two points
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
 #[allow(missing_docs)]
 pub struct PathItem {
     pub transform: Transform,
     pub path: Spline,
     pub stroke: Stroke,
+    /// The decoration at the start of the path
+    #[serde(default)]
+    pub start_marker: Marker,
+    /// The decoration at the end of the path
+    #[serde(default)]
+    pub end_marker: Marker,
 }
 
 /// An image stored in a URL
@@ -194,7 +383,16 @@ pub struct PathItem {
 pub struct ImageItem {
     pub transform: Transform,
     pub url: String,
+    /// Alternative text for the image
     pub description: String,
+    /// Filled in by the server from the stored file when the item is created or changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub info: Option<MediaInfo>,
+    /// The part of the image to show, in pixels, otherwise all of it is shown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub crop: Option<Rect>,
 }
 
 /// A text box, rendered with Markdown
@@ -206,6 +404,9 @@ pub struct ImageItem {
 pub struct TextItem {
     pub transform: Transform,
     pub text: String,
+    /// Missing from boards saved before text was styled, which are drawn with the defaults
+    #[serde(default)]
+    pub style: TextStyle,
 }
 
 /// A hyperlink
@@ -216,6 +417,48 @@ pub struct LinkItem {
     pub transform: Transform,
     pub url: String,
     pub text: String,
+    /// A board on this server to go to instead of `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub target: Option<BoardLink>,
+}
+
+impl LinkItem {
+    /// The text shown for the link, falling back to where it leads
+    pub fn label(&self) -> &str {
+        match &self.target {
+            _ if !self.text.is_empty() => &self.text,
+            Some(target) => &target.board,
+            None => &self.url,
+        }
+    }
+}
+
+/// A link to another board, or to a place on one
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
+pub struct BoardLink {
+    /// The name of the board when the link was last checked
+    pub board: String,
+    /// Filled in by the server when the link is created, and used to find the board if it is renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub board_id: Option<BoardID>,
+    /// Where to go on the board, otherwise it is opened as normal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub destination: Option<LinkDestination>,
+}
+
+/// A place on a board that a [`BoardLink`] leads to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum LinkDestination {
+    /// Bring an item into view
+    Item(ItemID),
+    /// Show an area of the board
+    Viewport(Rect),
 }
 
 /// An indexed tag
@@ -229,3 +472,401 @@ pub struct TagItem {
     /// The data associated with the tag
     pub data: String,
 }
+
+/// A line joining two other items, which follows them as they move
+///
+/// The ends are kept up to date by the server, and are only used as given when an attached item no longer exists
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectorItem {
+    /// The item the connector starts from
+    pub source: ItemID,
+    /// The item the connector leads to
+    pub target: ItemID,
+    /// Where the connector meets the source, relative to its bounding box:
+    /// `(-0.5, -0.5)` is the top-left corner and `(0.5, 0.5)` the bottom-right.
+    /// If missing, the connector meets the edge of the source facing the target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub source_anchor: Option<Point>,
+    /// See [`ConnectorItem::source_anchor`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub target_anchor: Option<Point>,
+    #[allow(missing_docs)]
+    pub start: Point,
+    #[allow(missing_docs)]
+    pub end: Point,
+    #[allow(missing_docs)]
+    pub stroke: Stroke,
+    /// The decoration at the source end
+    #[serde(default)]
+    pub start_marker: Marker,
+    /// The decoration at the target end
+    #[serde(default)]
+    pub end_marker: Marker,
+}
+
+/// A coloured note with text on it
+///
+/// NOTE: The size is implemented through the [`Transform`], instead of a separate property
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
+pub struct StickyNoteItem {
+    #[allow(missing_docs)]
+    pub transform: Transform,
+    /// The colour of the note itself
+    pub background: Color,
+    /// The text written on the note
+    pub text: String,
+    /// The name of whoever created the note, always set by the server
+    #[serde(default)]
+    pub author: String,
+    /// The largest font size (in pixels) to use, the text is shrunk below this to fit the note.
+    /// If missing, the text grows to fill the note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub font_size_hint: Option<f64>,
+}
+
+/// The most rows a [`TableItem`] may have
+pub const MAX_TABLE_ROWS: usize = 1_000;
+
+/// The most columns a [`TableItem`] may have
+pub const MAX_TABLE_COLUMNS: usize = 100;
+
+/// The longest text (in bytes) a cell of a [`TableItem`] may hold
+pub const MAX_CELL_LENGTH: usize = 10_000;
+
+/// A grid of text cells
+///
+/// The top-left corner of the table is at the origin of the [`Transform`],
+/// and the sizes of the rows and columns are measured along its basis vectors
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
+pub struct TableItem {
+    #[allow(missing_docs)]
+    pub transform: Transform,
+    /// The width of each column, which also sets the number of columns
+    pub column_widths: Vec<f64>,
+    /// The height of every row
+    pub row_height: f64,
+    /// The text of each cell, as a list of rows
+    pub cells: Vec<Vec<String>>,
+    /// The lines between cells
+    pub stroke: Stroke,
+}
+
+impl StickyNoteItem {
+    /// Check that the font size hint, if there is one, is a positive size
+    pub fn check_font_size_hint(&self) -> Result<(), RejectReason> {
+        match self.font_size_hint {
+            Some(hint) if !(hint.is_finite() && hint > 0.0) => Err(RejectReason::IncorrectType {
+                key: Some("fontSizeHint".to_string()),
+                expected: "positive number",
+                received: hint.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl TableItem {
+    /// Check that the sizes are positive, that every row has a cell for each column
+    /// and that the table and its text are within the size limits
+    pub fn check_layout(&self) -> Result<(), RejectReason> {
+        if self.cells.len() > MAX_TABLE_ROWS {
+            return Err(RejectReason::IncorrectType {
+                key: Some("cells".to_string()),
+                expected: "at most 1000 rows",
+                received: self.cells.len().to_string(),
+            });
+        }
+        if self.column_widths.len() > MAX_TABLE_COLUMNS {
+            return Err(RejectReason::IncorrectType {
+                key: Some("columnWidths".to_string()),
+                expected: "at most 100 columns",
+                received: self.column_widths.len().to_string(),
+            });
+        }
+        if let Some(cell) = self
+            .cells
+            .iter()
+            .flatten()
+            .find(|cell| cell.len() > MAX_CELL_LENGTH)
+        {
+            return Err(RejectReason::IncorrectType {
+                key: Some("cells".to_string()),
+                expected: "cell text of at most 10000 bytes",
+                received: format!("{} bytes", cell.len()),
+            });
+        }
+        let positive = |size: f64| size.is_finite() && size > 0.0;
+        if !positive(self.row_height) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("rowHeight".to_string()),
+                expected: "positive number",
+                received: self.row_height.to_string(),
+            });
+        }
+        if let Some(width) = self.column_widths.iter().find(|&&width| !positive(width)) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("columnWidths".to_string()),
+                expected: "positive number",
+                received: width.to_string(),
+            });
+        }
+        let columns = self.column_widths.len();
+        if let Some(row) = self.cells.iter().find(|row| row.len() != columns) {
+            return Err(RejectReason::IncorrectType {
+                key: Some("cells".to_string()),
+                expected: "a cell for each column",
+                received: format!("a row of {} cells for {columns} columns", row.len()),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_index(index: usize, len: usize, name: &str) -> Result<(), String> {
+        if index < len {
+            Ok(())
+        } else {
+            Err(format!("{name} {index} is out of range, there are {len}"))
+        }
+    }
+
+    fn check_cell(text: &str) -> Result<(), String> {
+        if text.len() > MAX_CELL_LENGTH {
+            Err(format!(
+                "Cells cannot be longer than {MAX_CELL_LENGTH} bytes"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The total width of the columns
+    pub fn width(&self) -> f64 {
+        self.column_widths.iter().sum()
+    }
+
+    /// The total height of the rows
+    pub fn height(&self) -> f64 {
+        self.row_height * self.cells.len() as f64
+    }
+
+    /// Insert a row before `index`, or at the end if `index` is the number of rows.
+    /// The row is padded or truncated to the number of columns
+    pub fn insert_row(&mut self, index: usize, mut cells: Vec<String>) -> Result<(), String> {
+        Self::check_index(index, self.cells.len() + 1, "Row")?;
+        if self.cells.len() >= MAX_TABLE_ROWS {
+            return Err(format!(
+                "Tables cannot have more than {MAX_TABLE_ROWS} rows"
+            ));
+        }
+        cells.resize(self.column_widths.len(), String::new());
+        cells.iter().try_for_each(|cell| Self::check_cell(cell))?;
+        self.cells.insert(index, cells);
+        Ok(())
+    }
+
+    #[allow(missing_docs)]
+    pub fn delete_row(&mut self, index: usize) -> Result<(), String> {
+        Self::check_index(index, self.cells.len(), "Row")?;
+        self.cells.remove(index);
+        Ok(())
+    }
+
+    /// Insert an empty column before `index`, or at the end if `index` is the number of columns
+    pub fn insert_column(&mut self, index: usize, width: f64) -> Result<(), String> {
+        Self::check_index(index, self.column_widths.len() + 1, "Column")?;
+        if self.column_widths.len() >= MAX_TABLE_COLUMNS {
+            return Err(format!(
+                "Tables cannot have more than {MAX_TABLE_COLUMNS} columns"
+            ));
+        }
+        if !(width.is_finite() && width > 0.0) {
+            return Err(format!("Column width must be positive, got {width}"));
+        }
+        self.column_widths.insert(index, width);
+        for row in &mut self.cells {
+            row.insert(index.min(row.len()), String::new());
+        }
+        Ok(())
+    }
+
+    #[allow(missing_docs)]
+    pub fn delete_column(&mut self, index: usize) -> Result<(), String> {
+        Self::check_index(index, self.column_widths.len(), "Column")?;
+        self.column_widths.remove(index);
+        for row in &mut self.cells {
+            if index < row.len() {
+                row.remove(index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the text of a single cell
+    pub fn set_cell(&mut self, row: usize, column: usize, text: String) -> Result<(), String> {
+        Self::check_index(row, self.cells.len(), "Row")?;
+        Self::check_index(column, self.column_widths.len(), "Column")?;
+        Self::check_cell(&text)?;
+        let cells = &mut self.cells[row];
+        if cells.len() <= column {
+            cells.resize(column + 1, String::new());
+        }
+        cells[column] = text;
+        Ok(())
+    }
+}
+
+/// A named area of the board, which carries the items inside it along when it moves
+///
+/// NOTE: The size is implemented through the [`Transform`], instead of a separate property
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct FrameItem {
+    #[allow(missing_docs)]
+    pub transform: Transform,
+    /// The title shown above the frame
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: usize, columns: usize) -> TableItem {
+        let cells: Vec<Vec<String>> = (0..rows)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| format!("{row},{column}"))
+                    .collect()
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "transform": {
+                "origin": { "x": 0.0, "y": 0.0 },
+                "basisX": { "x": 1.0, "y": 0.0 },
+                "basisY": { "x": 0.0, "y": 1.0 },
+            },
+            "columnWidths": vec![1.0; columns],
+            "rowHeight": 0.5,
+            "cells": cells,
+            "stroke": { "width": 0.01, "color": "#000000ff" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn insert_row_at_either_end() {
+        let mut table = table(2, 2);
+        table.insert_row(0, vec!["first".to_string()]).unwrap();
+        table
+            .insert_row(
+                3,
+                vec!["a", "b", "c"].into_iter().map(String::from).collect(),
+            )
+            .unwrap();
+        assert_eq!(table.cells.len(), 4);
+        assert_eq!(table.cells[0], ["first", ""]);
+        assert_eq!(table.cells[3], ["a", "b"]);
+        assert!(table.insert_row(5, Vec::new()).is_err());
+        assert_eq!(table.cells.len(), 4);
+        table.check_layout().unwrap();
+    }
+
+    #[test]
+    fn delete_row_at_either_end() {
+        let mut table = table(3, 1);
+        assert!(table.delete_row(3).is_err());
+        table.delete_row(2).unwrap();
+        table.delete_row(0).unwrap();
+        assert_eq!(table.cells, [["1,0"]]);
+        table.delete_row(0).unwrap();
+        assert!(table.cells.is_empty());
+        assert!(table.delete_row(0).is_err());
+    }
+
+    #[test]
+    fn insert_column_at_either_end() {
+        let mut table = table(2, 1);
+        table.insert_column(0, 2.0).unwrap();
+        table.insert_column(2, 3.0).unwrap();
+        assert_eq!(table.column_widths, [2.0, 1.0, 3.0]);
+        assert_eq!(table.cells[1], ["", "1,0", ""]);
+        assert!(table.insert_column(4, 1.0).is_err());
+        assert!(table.insert_column(0, 0.0).is_err());
+        assert!(table.insert_column(0, f64::NAN).is_err());
+        assert_eq!(table.column_widths.len(), 3);
+        table.check_layout().unwrap();
+    }
+
+    #[test]
+    fn delete_column_at_either_end() {
+        let mut table = table(1, 3);
+        assert!(table.delete_column(3).is_err());
+        table.delete_column(2).unwrap();
+        table.delete_column(0).unwrap();
+        assert_eq!(table.column_widths, [1.0]);
+        assert_eq!(table.cells, [["0,1"]]);
+        table.delete_column(0).unwrap();
+        assert!(table.column_widths.is_empty());
+        assert!(table.delete_column(0).is_err());
+        table.check_layout().unwrap();
+    }
+
+    #[test]
+    fn set_cell_in_range() {
+        let mut table = table(2, 2);
+        table.set_cell(1, 1, "changed".to_string()).unwrap();
+        assert_eq!(table.cells[1][1], "changed");
+        assert!(table.set_cell(2, 0, String::new()).is_err());
+        assert!(table.set_cell(0, 2, String::new()).is_err());
+    }
+
+    #[test]
+    fn check_layout_rejects_bad_tables() {
+        let mut ragged = table(2, 2);
+        ragged.cells[1].pop();
+        assert!(ragged.check_layout().is_err());
+
+        let mut flat = table(1, 1);
+        flat.row_height = 0.0;
+        assert!(flat.check_layout().is_err());
+
+        let mut infinite = table(1, 2);
+        infinite.column_widths[1] = f64::INFINITY;
+        assert!(infinite.check_layout().is_err());
+
+        assert!(table(MAX_TABLE_ROWS + 1, 1).check_layout().is_err());
+        assert!(table(1, MAX_TABLE_COLUMNS + 1).check_layout().is_err());
+
+        let mut long = table(1, 1);
+        long.cells[0][0] = "a".repeat(MAX_CELL_LENGTH + 1);
+        assert!(long.check_layout().is_err());
+    }
+
+    #[test]
+    fn edits_stay_within_limits() {
+        let mut tall = table(MAX_TABLE_ROWS, 1);
+        assert!(tall.insert_row(0, Vec::new()).is_err());
+        tall.delete_row(0).unwrap();
+        assert!(tall
+            .insert_row(0, vec!["a".repeat(MAX_CELL_LENGTH + 1)])
+            .is_err());
+        tall.insert_row(0, vec!["a".repeat(MAX_CELL_LENGTH)])
+            .unwrap();
+        assert!(tall
+            .set_cell(1, 0, "a".repeat(MAX_CELL_LENGTH + 1))
+            .is_err());
+
+        let mut wide = table(1, MAX_TABLE_COLUMNS);
+        assert!(wide.insert_column(0, 1.0).is_err());
+        wide.check_layout().unwrap();
+    }
+}
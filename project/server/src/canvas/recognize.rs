@@ -0,0 +1,417 @@
+//! Recognition of neat shapes in hand-drawn paths
+//!
+//! Tolerances are relative to the size of the drawing, so that small and large shapes are treated alike
+
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "codegen")]
+use ts_rs::TS;
+
+use super::{
+    geometry::{distance_to_segment, dot, douglas_peucker, length, sub},
+    item::{EllipseItem, LineItem, PolygonItem, RectangleItem},
+    Color, Fill, Item, Marker, Point, Rect, Stroke, Transform,
+};
+
+/// How far a straight line may wander, relative to its length
+const LINE_TOLERANCE: f64 = 0.06;
+
+/// How large the gap between the ends of a closed stroke may be, relative to its size
+const CLOSE_TOLERANCE: f64 = 0.2;
+
+/// How far a side of a polygon may wander, relative to the size of the shape
+const CORNER_TOLERANCE: f64 = 0.06;
+
+/// How far the cosine of a rectangle's corners may be from a right angle
+const RIGHT_ANGLE_TOLERANCE: f64 = 0.3;
+
+/// The average distance from an ellipse which is still recognized, relative to its radius
+const ELLIPSE_TOLERANCE: f64 = 0.08;
+
+/// The number of points an outline is resampled to when fitting an ellipse
+const ELLIPSE_SAMPLES: usize = 64;
+
+/// The most sides a recognized polygon can have
+const MAX_POLYGON_SIDES: usize = 8;
+
+/// Shapes within this angle (in radians) of being level are straightened
+const SNAP_ANGLE: f64 = PI / 18.0;
+
+/// A shape which a path was recognized as
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub enum RecognizedShape {
+    #[allow(missing_docs)]
+    Line,
+    #[allow(missing_docs)]
+    Rectangle,
+    #[allow(missing_docs)]
+    Ellipse,
+    #[allow(missing_docs)]
+    Polygon,
+}
+
+fn scale(v: Point, factor: f64) -> Point {
+    Point::new(v.x * factor, v.y * factor)
+}
+
+fn add(a: Point, b: Point) -> Point {
+    Point::new(a.x + b.x, a.y + b.y)
+}
+
+/// The direction at `angle`, and the direction a quarter turn from it
+fn axes(angle: f64) -> (Point, Point) {
+    let (sin, cos) = angle.sin_cos();
+    (Point::new(cos, sin), Point::new(-sin, cos))
+}
+
+/// Round an angle to the nearest quarter turn if it is close to one
+fn snap_angle(angle: f64) -> f64 {
+    let nearest = (angle / FRAC_PI_2).round() * FRAC_PI_2;
+    if (angle - nearest).abs() <= SNAP_ANGLE {
+        nearest
+    } else {
+        angle
+    }
+}
+
+/// The corners of a closed outline, without repeating the first corner at the end
+fn corners(points: &[Point], tolerance: f64) -> Vec<Point> {
+    // Split the loop at the point furthest from the start, so neither half is closed
+    let Some(far) = (0..points.len()).max_by(|&a, &b| {
+        length(sub(points[a], points[0])).total_cmp(&length(sub(points[b], points[0])))
+    }) else {
+        return Vec::new();
+    };
+    let mut outline: Vec<_> = points.to_vec();
+    outline.push(points[0]);
+    let mut corners = Vec::new();
+    for half in [&outline[..=far], &outline[far..]] {
+        let keep = douglas_peucker(half, tolerance);
+        corners.extend(
+            half.iter()
+                .zip(keep)
+                .skip(1)
+                .filter_map(|(&p, keep)| keep.then_some(p)),
+        );
+    }
+    // The end of the loop is the start again, which may not be a corner
+    corners.pop();
+    corners.insert(0, points[0]);
+
+    // Drop corners which are barely turned or which nearly meet their neighbour
+    let mut i = 0;
+    while corners.len() > 3 && i < corners.len() {
+        let prev = corners[(i + corners.len() - 1) % corners.len()];
+        let next = corners[(i + 1) % corners.len()];
+        if distance_to_segment(corners[i], prev, next) <= tolerance
+            || length(sub(corners[i], prev)) <= tolerance
+        {
+            corners.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+    corners
+}
+
+/// Points spaced evenly along a closed outline, so that slowly drawn parts don't count for more
+fn resample_closed(points: &[Point], count: usize) -> Vec<Point> {
+    let mut outline = points.to_vec();
+    outline.push(points[0]);
+    let total: f64 = outline.windows(2).map(|w| length(sub(w[1], w[0]))).sum();
+    let step = total / count as f64;
+    let mut samples = Vec::with_capacity(count);
+    let mut travelled = 0.0;
+    for pair in outline.windows(2) {
+        let segment = length(sub(pair[1], pair[0]));
+        while samples.len() < count && (samples.len() as f64) * step <= travelled + segment {
+            let t = match segment {
+                0.0 => 0.0,
+                _ => ((samples.len() as f64) * step - travelled) / segment,
+            };
+            samples.push(add(pair[0], scale(sub(pair[1], pair[0]), t)));
+        }
+        travelled += segment;
+    }
+    samples
+}
+
+fn recognize_rectangle(corners: &[Point]) -> Option<Transform> {
+    let [c0, c1, c2, c3] = corners else {
+        return None;
+    };
+    for i in 0..4 {
+        let corner = corners[i];
+        let a = sub(corners[(i + 3) % 4], corner);
+        let b = sub(corners[(i + 1) % 4], corner);
+        if (dot(a, b) / (length(a) * length(b))).abs() > RIGHT_ANGLE_TOLERANCE {
+            return None;
+        }
+    }
+    let side_x = scale(add(sub(*c1, *c0), sub(*c2, *c3)), 0.5);
+    let side_y = scale(add(sub(*c3, *c0), sub(*c2, *c1)), 0.5);
+    let (axis_x, axis_y) = axes(snap_angle(side_x.y.atan2(side_x.x)));
+    Some(Transform {
+        origin: scale(add(add(*c0, *c1), add(*c2, *c3)), 0.25),
+        basis_x: scale(axis_x, length(side_x)),
+        basis_y: scale(axis_y, length(side_y)),
+    })
+}
+
+fn recognize_ellipse(points: &[Point]) -> Option<Transform> {
+    let samples = resample_closed(points, ELLIPSE_SAMPLES);
+    let count = samples.len() as f64;
+    let mean = scale(
+        samples.iter().fold(Point::default(), |a, &b| add(a, b)),
+        1.0 / count,
+    );
+
+    // The principal axis of the points is along the ellipse's longest diameter
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+    for p in &samples {
+        let d = sub(*p, mean);
+        xx += d.x * d.x;
+        xy += d.x * d.y;
+        yy += d.y * d.y;
+    }
+    let (axis_x, axis_y) = axes(snap_angle(0.5 * (2.0 * xy).atan2(xx - yy)));
+
+    let local = |p: Point| Point::new(dot(p, axis_x), dot(p, axis_y));
+    let extent = Rect::from_points(samples.iter().map(|&p| local(p)))?;
+    let radius_x = extent.width() / 2.0;
+    let radius_y = extent.height() / 2.0;
+    if radius_x == 0.0 || radius_y == 0.0 {
+        return None;
+    }
+    let centre = Point::new(
+        (extent.min.x + extent.max.x) / 2.0,
+        (extent.min.y + extent.max.y) / 2.0,
+    );
+
+    let error = samples
+        .iter()
+        .map(|&p| {
+            let d = sub(local(p), centre);
+            ((d.x / radius_x).hypot(d.y / radius_y) - 1.0).abs()
+        })
+        .sum::<f64>()
+        / count;
+    (error <= ELLIPSE_TOLERANCE).then(|| Transform {
+        origin: add(scale(axis_x, centre.x), scale(axis_y, centre.y)),
+        basis_x: scale(axis_x, radius_x * 2.0),
+        basis_y: scale(axis_y, radius_y * 2.0),
+    })
+}
+
+/// A stroke which looks the same as `stroke` once scaled by `transform`
+fn unscaled_stroke(stroke: &Stroke, transform: &Transform) -> Stroke {
+    Stroke {
+        width: stroke.width / transform.scale_factor(),
+        ..stroke.clone()
+    }
+}
+
+/// Try to match a hand-drawn line to a neat shape, returning the item to draw instead
+pub fn recognize_shape(points: &[Point], stroke: &Stroke) -> Option<(RecognizedShape, Item)> {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| a.x == b.x && a.y == b.y);
+    let (&first, &last) = (points.first()?, points.last()?);
+    let bounds = Rect::from_points(points.iter().copied())?;
+    let size = bounds.width().hypot(bounds.height());
+    if size == 0.0 {
+        return None;
+    }
+
+    let gap = length(sub(last, first));
+    if points
+        .iter()
+        .all(|&p| distance_to_segment(p, first, last) <= LINE_TOLERANCE * gap)
+    {
+        let item = LineItem {
+            start: first,
+            end: last,
+            stroke: stroke.clone(),
+            start_marker: Marker::None,
+            end_marker: Marker::None,
+        };
+        return Some((RecognizedShape::Line, Item::Line(item)));
+    }
+
+    if gap > CLOSE_TOLERANCE * size {
+        return None;
+    }
+    let fill = Fill::Solid(Color::from_rgba(0, 0, 0, 0));
+    let corners = corners(&points, CORNER_TOLERANCE * size);
+
+    if let Some(transform) = recognize_rectangle(&corners) {
+        let item = RectangleItem {
+            stroke: unscaled_stroke(stroke, &transform),
+            transform,
+            fill,
+        };
+        return Some((RecognizedShape::Rectangle, Item::Rectangle(item)));
+    }
+    if let Some(transform) = recognize_ellipse(&points) {
+        let item = EllipseItem {
+            stroke: unscaled_stroke(stroke, &transform),
+            transform,
+            fill,
+        };
+        return Some((RecognizedShape::Ellipse, Item::Ellipse(item)));
+    }
+    if (3..=MAX_POLYGON_SIDES).contains(&corners.len()) {
+        let item = PolygonItem {
+            points: corners,
+            stroke: stroke.clone(),
+            fill,
+        };
+        return Some((RecognizedShape::Polygon, Item::Polygon(item)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke() -> Stroke {
+        serde_json::from_value(serde_json::json!({ "width": 0.1, "color": "#000000ff" })).unwrap()
+    }
+
+    fn assert_near(a: Point, b: Point, tolerance: f64) {
+        assert!(length(sub(a, b)) <= tolerance, "{a:?} is not near {b:?}");
+    }
+
+    /// Points every `step` along straight lines between the corners, wobbling slightly like a hand-drawn stroke
+    fn trace(corners: &[Point], step: f64) -> Vec<Point> {
+        let mut points = Vec::new();
+        for pair in corners.windows(2) {
+            let d = sub(pair[1], pair[0]);
+            let count = (length(d) / step).ceil() as usize;
+            for i in 0..count {
+                let t = i as f64 / count as f64;
+                let wobble = (points.len() as f64 * 0.7).sin() * 0.01;
+                points.push(Point::new(
+                    pair[0].x + d.x * t + wobble,
+                    pair[0].y + d.y * t - wobble,
+                ));
+            }
+        }
+        points.push(*corners.last().unwrap());
+        points
+    }
+
+    /// The corners of a `width` by `height` box centred on `centre`, rotated by `angle` radians, starting and ending at the same corner
+    fn rotated_box(centre: Point, width: f64, height: f64, angle: f64) -> Vec<Point> {
+        let (axis_x, axis_y) = axes(angle);
+        let corner = |x: f64, y: f64| add(centre, add(scale(axis_x, x), scale(axis_y, y)));
+        let (w, h) = (width / 2.0, height / 2.0);
+        vec![
+            corner(-w, -h),
+            corner(w, -h),
+            corner(w, h),
+            corner(-w, h),
+            corner(-w, -h),
+        ]
+    }
+
+    #[test]
+    fn straight_line() {
+        let points = trace(&[Point::new(0.0, 0.0), Point::new(5.0, 2.0)], 0.1);
+        let (shape, item) = recognize_shape(&points, &stroke()).unwrap();
+        assert_eq!(shape, RecognizedShape::Line);
+        let Item::Line(line) = item else {
+            panic!("expected a line, got {item:?}")
+        };
+        assert_near(line.start, Point::new(0.0, 0.0), 1e-9);
+        assert_near(line.end, Point::new(5.0, 2.0), 1e-9);
+    }
+
+    #[test]
+    fn slightly_rotated_box_is_straightened() {
+        let corners = rotated_box(Point::new(2.0, 1.0), 4.0, 2.0, 3f64.to_radians());
+        let (shape, item) = recognize_shape(&trace(&corners, 0.05), &stroke()).unwrap();
+        assert_eq!(shape, RecognizedShape::Rectangle);
+        let Item::Rectangle(rect) = item else {
+            panic!("expected a rectangle, got {item:?}")
+        };
+        assert_near(rect.transform.origin, Point::new(2.0, 1.0), 0.05);
+        assert_near(rect.transform.basis_x, Point::new(4.0, 0.0), 0.1);
+        assert_near(rect.transform.basis_y, Point::new(0.0, 2.0), 0.1);
+        // The stroke is scaled along with the rectangle, so it is narrowed to look the same
+        assert!(rect.stroke.width < 0.1);
+    }
+
+    #[test]
+    fn rotated_box_keeps_its_angle() {
+        let angle = 30f64.to_radians();
+        let corners = rotated_box(Point::new(0.0, 0.0), 4.0, 2.0, angle);
+        let (shape, item) = recognize_shape(&trace(&corners, 0.05), &stroke()).unwrap();
+        assert_eq!(shape, RecognizedShape::Rectangle);
+        let Item::Rectangle(rect) = item else {
+            panic!("expected a rectangle, got {item:?}")
+        };
+        let (axis_x, axis_y) = axes(angle);
+        assert_near(rect.transform.origin, Point::new(0.0, 0.0), 0.05);
+        assert_near(rect.transform.basis_x, scale(axis_x, 4.0), 0.15);
+        assert_near(rect.transform.basis_y, scale(axis_y, 2.0), 0.15);
+    }
+
+    #[test]
+    fn circle() {
+        let points: Vec<_> = (0..=100)
+            .map(|i| {
+                let angle = i as f64 / 100.0 * 2.0 * PI;
+                Point::new(1.0 + 2.0 * angle.cos(), -1.0 + 2.0 * angle.sin())
+            })
+            .collect();
+        let (shape, item) = recognize_shape(&points, &stroke()).unwrap();
+        assert_eq!(shape, RecognizedShape::Ellipse);
+        let Item::Ellipse(ellipse) = item else {
+            panic!("expected an ellipse, got {item:?}")
+        };
+        assert_near(ellipse.transform.origin, Point::new(1.0, -1.0), 0.05);
+        assert!((length(ellipse.transform.basis_x) - 4.0).abs() < 0.1);
+        assert!((length(ellipse.transform.basis_y) - 4.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn triangle() {
+        let vertices = [
+            Point::new(0.0, 0.0),
+            Point::new(4.0, 0.0),
+            Point::new(2.0, 3.0),
+            Point::new(0.0, 0.0),
+        ];
+        let (shape, item) = recognize_shape(&trace(&vertices, 0.05), &stroke()).unwrap();
+        assert_eq!(shape, RecognizedShape::Polygon);
+        let Item::Polygon(polygon) = item else {
+            panic!("expected a polygon, got {item:?}")
+        };
+        assert_eq!(polygon.points.len(), 3);
+        for vertex in &vertices[..3] {
+            assert!(
+                polygon
+                    .points
+                    .iter()
+                    .any(|&p| length(sub(p, *vertex)) < 0.1),
+                "no corner near {vertex:?} in {:?}",
+                polygon.points
+            );
+        }
+    }
+
+    #[test]
+    fn open_squiggle_is_not_recognized() {
+        let points: Vec<_> = (0..200)
+            .map(|i| {
+                let x = i as f64 * 0.05;
+                Point::new(x, (x * 3.0).sin())
+            })
+            .collect();
+        assert!(recognize_shape(&points, &stroke()).is_none());
+    }
+}
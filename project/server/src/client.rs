@@ -1,9 +1,21 @@
 //! Interfacing with clients
 //! The main interface of this module is [`create_client_filter`], which builds a filter to forward WebSocket requests to a board
 
-use futures_util::{SinkExt, StreamExt};
-use log::{error, info, warn};
-use tokio::sync::mpsc;
+mod poll;
+
+use std::{
+    collections::VecDeque,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{
+    future::{select, Either},
+    SinkExt, StreamExt,
+};
+use log::{debug, error, info, warn};
+use tokio::{sync::Notify, time::Instant};
 use warp::{
     filters::{
         ws::{Message, WebSocket, Ws},
@@ -16,60 +28,302 @@ use warp::{
 
 use crate::{
     board::BoardHandle,
-    message::{ClientID, ClientInfo, MsgRecv, MsgSend, SessionID},
-    GlobalRes,
+    message::{notify_c::NotifyC, ClientID, ClientInfo, MsgRecv, MsgSend, SessionID},
+    Configuration, GlobalRes,
 };
 
+/// Number of queued messages above which a client is considered to be falling behind
+pub const CLIENT_QUEUE_THRESHOLD: usize = 256;
+
+/// Number of queued messages at which a client is disconnected immediately
+pub const CLIENT_QUEUE_LIMIT: usize = 4096;
+
+/// How long a client's queue may stay above [`CLIENT_QUEUE_THRESHOLD`] before it is disconnected
+pub const CLIENT_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies messages which are made obsolete by a newer message with the same key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CoalesceKey {
+    SelectionMoved(ClientID),
+}
+
+impl CoalesceKey {
+    fn from_msg(msg: &MsgSend) -> Option<Self> {
+        match msg {
+            MsgSend::NotifyC(notify) => Self::from_notify(notify),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_notify(notify: &NotifyC) -> Option<Self> {
+        match notify {
+            // Moves which update item transforms can't be dropped without losing information
+            NotifyC::SelectionMoved(notify) if notify.new_sits.is_none() => {
+                Some(Self::SelectionMoved(notify.id))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// An opaque payload that can be duplicated and sent to multiple clients
-pub struct MessagePayload(Vec<u8>);
+pub struct MessagePayload(Vec<u8>, Option<CoalesceKey>);
 
 impl MessagePayload {
     /// Create a new stored payload from the send message
     pub fn new(msg: &MsgSend) -> Self {
-        Self(serde_json::to_vec(msg).expect("Failed to serialize payload"))
+        Self(
+            serde_json::to_vec(msg).expect("Failed to serialize payload"),
+            CoalesceKey::from_msg(msg),
+        )
+    }
+}
+
+/// The reason the server closed a connection
+#[derive(Debug, Clone, Copy)]
+pub enum DisconnectReason {
+    /// The client's queue reached [`CLIENT_QUEUE_LIMIT`]
+    QueueFull,
+    /// The client's queue stayed above [`CLIENT_QUEUE_THRESHOLD`] for longer than [`CLIENT_QUEUE_TIMEOUT`]
+    QueueStalled,
+}
+
+impl DisconnectReason {
+    /// The WebSocket close code sent to the client
+    fn code(&self) -> u16 {
+        // Policy Violation
+        1008
+    }
+
+    /// The explanation sent with the close frame
+    fn message(&self) -> &'static str {
+        match self {
+            Self::QueueFull => "Too many messages queued",
+            Self::QueueStalled => "Client is not receiving messages fast enough",
+        }
     }
 }
 
+#[derive(Debug)]
 enum ClientMessage {
-    Payload(Vec<u8>),
+    Payload(Vec<u8>, Option<CoalesceKey>),
+    Close(DisconnectReason),
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    messages: VecDeque<ClientMessage>,
+    over_threshold_since: Option<Instant>,
+    closed: bool,
+}
+
+impl QueueState {
+    /// Discard anything still waiting and replace it with a close message
+    fn overflow(&mut self, reason: DisconnectReason) {
+        warn!("Disconnecting client: {}", reason.message());
+        self.messages.clear();
+        self.messages.push_back(ClientMessage::Close(reason));
+        self.closed = true;
+    }
+
+    /// Close the queue if it has stayed above [`CLIENT_QUEUE_THRESHOLD`] for too long, returning whether it did
+    fn check_stalled(&mut self, now: Instant) -> bool {
+        match self.over_threshold_since {
+            Some(since) if !self.closed && now - since > CLIENT_QUEUE_TIMEOUT => {
+                self.overflow(DisconnectReason::QueueStalled);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A bounded queue of messages waiting to be sent to a client
+#[derive(Debug, Default)]
+struct ClientQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    /// Woken when the queue is closed, see [`ClientQueue::closed`]
+    closed_notify: Notify,
+    /// Set for collectors, whose messages reach the client as a single message once they are taken
+    unbounded: bool,
+}
+
+impl ClientQueue {
+    fn push(&self, message: ClientMessage) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            debug!("Dropping message for closed client");
+            return;
+        }
+
+        if let ClientMessage::Payload(_, Some(key)) = &message {
+            state.messages.retain(|queued| match queued {
+                ClientMessage::Payload(_, Some(other)) => other != key,
+                _ => true,
+            });
+        }
+
+        state.messages.push_back(message);
+
+        let len = state.messages.len();
+        let closed = if self.unbounded {
+            false
+        } else if len >= CLIENT_QUEUE_LIMIT {
+            state.overflow(DisconnectReason::QueueFull);
+            true
+        } else if len > CLIENT_QUEUE_THRESHOLD {
+            let now = Instant::now();
+            state.over_threshold_since.get_or_insert(now);
+            state.check_stalled(now)
+        } else {
+            false
+        };
+
+        drop(state);
+        self.notify.notify_one();
+        if closed {
+            self.closed_notify.notify_waiters();
+        }
+    }
+
+    /// Disconnect the client if its queue stays above [`CLIENT_QUEUE_THRESHOLD`], even if nothing more is sent to it
+    ///
+    /// Returns once the queue is closed
+    async fn watch_stalled(self: Arc<Self>) {
+        loop {
+            let deadline = {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return;
+                }
+                let now = Instant::now();
+                if state.check_stalled(now) {
+                    drop(state);
+                    self.notify.notify_one();
+                    self.closed_notify.notify_waiters();
+                    return;
+                }
+                match state.over_threshold_since {
+                    Some(since) => since + CLIENT_QUEUE_TIMEOUT,
+                    // Checked more often so that a queue which fills up is noticed soon after it times out
+                    None => now + CLIENT_QUEUE_TIMEOUT / 4,
+                }
+            };
+            tokio::time::sleep_until(deadline + Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Wait until the queue is closed, either by [`ClientQueue::close`] or because the client fell behind
+    async fn closed(&self) {
+        loop {
+            // Registered before checking so that a close in between isn't missed
+            let notified = self.closed_notify.notified();
+            if self.state.lock().unwrap().closed {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Wait for the next message, returning [`None`] once the queue is closed and empty
+    async fn pop(&self) -> Option<ClientMessage> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(message) = state.messages.pop_front() {
+                    if state.messages.len() <= CLIENT_QUEUE_THRESHOLD {
+                        state.over_threshold_since = None;
+                    }
+                    return Some(message);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+        self.closed_notify.notify_waiters();
+    }
+
+    /// Take every queued message without waiting
+    fn take_ready(&self) -> Vec<ClientMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.over_threshold_since = None;
+        state.messages.drain(..).collect()
+    }
+
+    /// Take every queued payload without waiting
+    fn drain_payloads(&self) -> Vec<Vec<u8>> {
+        self.take_ready()
+            .into_iter()
+            .filter_map(|message| match message {
+                ClientMessage::Payload(data, _) => Some(data),
+                ClientMessage::Close(_) => None,
+            })
+            .collect()
+    }
 }
 
 /// A handle used to send messages mack to a client
 #[derive(Debug, Clone)]
 pub struct ClientHandle {
-    message_pipe: mpsc::UnboundedSender<ClientMessage>,
+    queue: Arc<ClientQueue>,
 }
 
 impl ClientHandle {
-    fn new() -> (Self, mpsc::UnboundedReceiver<ClientMessage>) {
-        let (sender, receiver) = mpsc::unbounded_channel();
+    fn new() -> (Self, Arc<ClientQueue>) {
+        Self::with_queue(ClientQueue::default())
+    }
+
+    fn with_queue(queue: ClientQueue) -> (Self, Arc<ClientQueue>) {
+        let queue = Arc::new(queue);
         (
             Self {
-                message_pipe: sender,
+                queue: queue.clone(),
             },
-            receiver,
+            queue,
         )
     }
 
     fn send(&self, message: ClientMessage) {
-        self.message_pipe.send(message).unwrap_or_else(|e| {
-            warn!("Failed to dispatch message to client: {e}");
-        })
+        self.queue.push(message)
     }
 
-    fn send_data(&self, data: Vec<u8>) {
-        self.send(ClientMessage::Payload(data))
+    fn send_data(&self, data: Vec<u8>, key: Option<CoalesceKey>) {
+        self.send(ClientMessage::Payload(data, key))
     }
 
     /// Dispatch a message to a client
     pub fn send_message(&self, message: MsgSend) {
         let payload = MessagePayload::new(&message);
-        self.send_data(payload.0);
+        self.send_data(payload.0, payload.1);
     }
 
     /// Send a copy of an existing [`MessagePayload`]
     pub fn send_payload(&self, payload: &MessagePayload) {
-        self.send_data(payload.0.clone())
+        self.send_data(payload.0.clone(), payload.1)
+    }
+
+    /// Create a handle which is not connected to a client and stores messages until [`Self::take_payloads`] is called
+    ///
+    /// Collected messages don't count towards [`CLIENT_QUEUE_LIMIT`], since they are sent on as one message
+    pub fn new_collector() -> Self {
+        Self::with_queue(ClientQueue {
+            unbounded: true,
+            ..Default::default()
+        })
+        .0
+    }
+
+    /// Remove all messages stored in a collector, returning them as serialized JSON
+    pub fn take_payloads(&self) -> Vec<Vec<u8>> {
+        self.queue.drain_payloads()
     }
 }
 
@@ -80,6 +334,8 @@ pub static MAX_SESSION_CREATE_LENGTH: u64 = 1024;
 struct Session {
     client_id: ClientID,
     handle: BoardHandle,
+    /// The connection state if the client is using [`poll`] instead of a WebSocket
+    poll: Arc<Mutex<poll::PollState>>,
 }
 
 impl Session {
@@ -94,6 +350,10 @@ impl Session {
     fn message(&self, msg: MsgRecv) {
         self.handle.client_msg(self.client_id, msg)
     }
+
+    fn latency(&self, latency: Duration) {
+        self.handle.client_latency(self.client_id, latency)
+    }
 }
 
 type RegistryInner = tokio::sync::RwLock<std::collections::HashMap<SessionID, Session>>;
@@ -104,6 +364,7 @@ pub struct SessionRegistry(RegistryInner);
 
 fn create_session_filter(
     registry: &'static RegistryInner,
+    config: &'static Configuration,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> {
     warp::path("session")
         .and(warp::path::param())
@@ -112,7 +373,7 @@ fn create_session_filter(
             let sessions = registry.read().await;
             if let Some(session) = sessions.get(&id) {
                 let session = session.clone();
-                Ok(ws.on_upgrade(|ws| async { handle_session(session, ws).await }))
+                Ok(ws.on_upgrade(|ws| async { handle_session(session, ws, config).await }))
             } else {
                 Err(warp::reject())
             }
@@ -121,56 +382,116 @@ fn create_session_filter(
 
 /// Create the board route as a [`Filter`]
 pub fn create_client_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
-    let session = create_session_filter(&res.sessions.0);
+    let session = create_session_filter(&res.sessions.0, &res.config);
 
-    let session_create: _ = warp::path!("board" / String)
+    let session_create = warp::path!("board" / String)
         .and(warp::body::content_length_limit(MAX_SESSION_CREATE_LENGTH))
         .and(warp::body::json())
         .then(|name, info: ClientInfo| async {
-            let handle = res.boards.load_board(name).await;
-            let session = handle.create_session(info).await;
-            if let Ok(info) = &session {
-                if let Some(_) = res.sessions.0.write().await.insert(
-                    info.session_id,
-                    Session {
-                        client_id: info.client_id,
-                        handle,
-                    },
-                ) {
-                    error!("Duplicate session ID: {:?}", info.session_id);
+            let session = match res.boards.load_board(name).await {
+                Ok(handle) => {
+                    let session = handle.create_session(info).await;
+                    if let Ok(info) = &session {
+                        let replaced = res.sessions.0.write().await.insert(
+                            info.session_id,
+                            Session {
+                                client_id: info.client_id,
+                                handle,
+                                poll: Default::default(),
+                            },
+                        );
+                        if replaced.is_some() {
+                            error!("Duplicate session ID: {:?}", info.session_id);
+                        }
+                    }
+                    session
                 }
-            }
-            serde_json::to_string(&Result::from(session)).unwrap_or_else(|e| {
+                Err(e) => Err(e),
+            };
+            serde_json::to_string(&session).unwrap_or_else(|e| {
                 error!("Failed to serialize response: {e}");
                 String::new()
             })
         });
-    session.or(session_create).boxed()
+    let poll = poll::create_poll_filter(&res.sessions.0, &res.config);
+
+    session.or(poll).or(session_create).boxed()
+}
+
+/// Encode the time since `start` as a ping payload
+fn ping_payload(start: Instant) -> Vec<u8> {
+    (start.elapsed().as_micros() as u64).to_be_bytes().to_vec()
+}
+
+/// Recover the round-trip time from a pong echoing a payload from [`ping_payload`]
+fn pong_latency(start: Instant, payload: &[u8]) -> Option<Duration> {
+    let sent = Duration::from_micros(u64::from_be_bytes(payload.try_into().ok()?));
+    start.elapsed().checked_sub(sent)
 }
 
-async fn handle_session(session: Session, ws: WebSocket) {
+async fn handle_session(session: Session, ws: WebSocket, config: &Configuration) {
     let (mut tx, mut rx) = ws.split();
 
-    let (handle, mut board_recv) = ClientHandle::new();
+    let (handle, queue) = ClientHandle::new();
 
     session.connect(handle);
+    tokio::task::spawn(queue.clone().watch_stalled());
 
+    let start = Instant::now();
+    let interval = config.heartbeat_interval;
+
+    let send_queue = queue.clone();
     tokio::task::spawn(async move {
-        while let Some(msg) = board_recv.recv().await {
+        let mut next_ping = start + interval;
+        loop {
+            let msg = match tokio::time::timeout_at(next_ping, send_queue.pop()).await {
+                Ok(Some(msg)) => Some(msg),
+                Ok(None) => break,
+                Err(_) => None,
+            };
+
             match msg {
-                ClientMessage::Payload(msg) => {
+                Some(ClientMessage::Payload(msg, _)) => {
                     tx.send(Message::binary(msg))
                         .await
                         .unwrap_or_else(|e| warn!("Failed to send WebSocket message: {e}"));
                 }
+                Some(ClientMessage::Close(reason)) => {
+                    // The read loop stops once the queue is closed and disconnects the session
+                    tx.send(Message::close_with(reason.code(), reason.message()))
+                        .await
+                        .unwrap_or_else(|e| warn!("Failed to send WebSocket close: {e}"));
+                    break;
+                }
+                None => {}
+            }
+
+            if Instant::now() >= next_ping {
+                tx.send(Message::ping(ping_payload(start)))
+                    .await
+                    .unwrap_or_else(|e| warn!("Failed to send WebSocket ping: {e}"));
+                next_ping = Instant::now() + interval;
             }
         }
     });
 
-    while let Some(Ok(msg)) = rx.next().await {
+    loop {
+        // Any message, including pongs, shows that the connection is still alive
+        let next = tokio::time::timeout(config.heartbeat_timeout, rx.next());
+        // Stop reading once the client has been disconnected for falling behind
+        let msg = match select(pin!(next), pin!(queue.closed())).await {
+            Either::Left((Ok(Some(Ok(msg))), _)) => msg,
+            _ => break,
+        };
         if msg.is_close() {
-            session.disconnect();
             info!("Socket closed");
+            break;
+        } else if msg.is_pong() {
+            if let Some(latency) = pong_latency(start, msg.as_bytes()) {
+                session.latency(latency);
+            }
+        } else if msg.is_ping() {
+            // Replied to automatically
         } else {
             match serde_json::from_slice(msg.as_bytes()) {
                 Ok(msg) => session.message(msg),
@@ -183,4 +504,80 @@ async fn handle_session(session: Session, ws: WebSocket) {
             }
         }
     }
+
+    session.disconnect();
+    queue.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> ClientMessage {
+        ClientMessage::Payload(Vec::new(), None)
+    }
+
+    #[test]
+    fn full_queue_is_replaced_by_close() {
+        let queue = ClientQueue::default();
+        for _ in 0..CLIENT_QUEUE_LIMIT {
+            queue.push(payload());
+        }
+        queue.push(payload());
+
+        let messages = queue.take_ready();
+        assert!(matches!(
+            messages[..],
+            [ClientMessage::Close(DisconnectReason::QueueFull)]
+        ));
+        assert!(queue.state.lock().unwrap().closed);
+    }
+
+    #[test]
+    fn collector_keeps_every_message() {
+        let collector = ClientHandle::new_collector();
+        for _ in 0..=CLIENT_QUEUE_LIMIT {
+            collector.send(payload());
+        }
+
+        assert_eq!(collector.take_payloads().len(), CLIENT_QUEUE_LIMIT + 1);
+    }
+
+    #[test]
+    fn coalesced_messages_replace_older_ones() {
+        let queue = ClientQueue::default();
+        let key = Some(CoalesceKey::SelectionMoved(ClientID::new()));
+        queue.push(ClientMessage::Payload(vec![1], key));
+        queue.push(payload());
+        queue.push(ClientMessage::Payload(vec![2], key));
+
+        let data: Vec<_> = queue.drain_payloads();
+        assert_eq!(data, vec![vec![], vec![2]]);
+    }
+
+    #[test]
+    fn queue_stalls_only_after_timeout() {
+        let start = Instant::now();
+        let mut state = QueueState {
+            over_threshold_since: Some(start),
+            ..Default::default()
+        };
+
+        assert!(!state.check_stalled(start + CLIENT_QUEUE_TIMEOUT));
+        assert!(!state.closed);
+
+        assert!(state.check_stalled(start + CLIENT_QUEUE_TIMEOUT * 2));
+        assert!(state.closed);
+        assert!(matches!(
+            state.messages.make_contiguous(),
+            [ClientMessage::Close(DisconnectReason::QueueStalled)]
+        ));
+    }
+
+    #[test]
+    fn queue_below_threshold_never_stalls() {
+        let start = Instant::now();
+        let mut state = QueueState::default();
+        assert!(!state.check_stalled(start + CLIENT_QUEUE_TIMEOUT * 2));
+    }
 }
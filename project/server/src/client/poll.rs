@@ -0,0 +1,228 @@
+//! A fallback transport for clients which can't use WebSockets
+//!
+//! Messages are sent by POSTing a [`MsgRecv`] to `session/<id>/send`, and received by long-polling `session/<id>/poll`,
+//! which responds with a JSON array of [`crate::message::MsgSend`] once any are available.
+//! A client which doesn't poll for [`Configuration::heartbeat_timeout`] is disconnected,
+//! after which both routes respond with `410 Gone` and the client has to create a new session.
+//! Only one poll may be waiting at a time, others get `409 Conflict`.
+
+use std::sync::Arc;
+
+use log::info;
+use tokio::time::Instant;
+use warp::{
+    http::StatusCode,
+    reject::Rejection,
+    reply::{Reply, Response},
+    Filter,
+};
+
+use crate::{
+    message::{MsgRecv, SessionID},
+    Configuration,
+};
+
+use super::{ClientHandle, ClientMessage, ClientQueue, DisconnectReason, RegistryInner, Session};
+
+/// Max request body length for sending messages (4MiB)
+pub static MAX_POLL_SEND_LENGTH: u64 = 4 * 1024 * 1024;
+
+/// The long-polling connection of a session
+#[derive(Default)]
+pub(super) enum PollState {
+    /// The session hasn't used long-polling
+    #[default]
+    Unused,
+    /// The session is receiving messages through long-polling
+    Connected(PollConnection),
+    /// The connection timed out or was closed, so the session can't be used for long-polling again
+    Ended,
+}
+
+pub(super) struct PollConnection {
+    queue: Arc<ClientQueue>,
+    last_seen: Instant,
+    /// Whether a poll is currently waiting for messages
+    polling: bool,
+}
+
+/// Marks a poll as finished when dropped, even if the request was abandoned
+struct PollGuard(Session);
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        if let PollState::Connected(state) = &mut *self.0.poll.lock().unwrap() {
+            state.polling = false;
+            state.last_seen = Instant::now();
+        }
+    }
+}
+
+impl Session {
+    /// Get the session's long-polling connection, connecting it if it hasn't been used yet
+    ///
+    /// Returns [`None`] if the connection has ended
+    fn poll_connection<'a>(
+        &self,
+        state: &'a mut PollState,
+        config: &'static Configuration,
+    ) -> Option<&'a mut PollConnection> {
+        if let PollState::Unused = state {
+            let (handle, queue) = ClientHandle::new();
+            self.connect(handle);
+            tokio::task::spawn(watch_poll(self.clone(), config));
+            tokio::task::spawn(queue.clone().watch_stalled());
+            *state = PollState::Connected(PollConnection {
+                queue,
+                last_seen: Instant::now(),
+                polling: false,
+            });
+        }
+
+        match state {
+            PollState::Connected(state) => {
+                state.last_seen = Instant::now();
+                Some(state)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check that the session can still send messages, connecting it if necessary
+    fn touch_poll(&self, config: &'static Configuration) -> bool {
+        let mut state = self.poll.lock().unwrap();
+        self.poll_connection(&mut state, config).is_some()
+    }
+
+    /// Claim the session's queue for a single poll
+    fn begin_poll(
+        &self,
+        config: &'static Configuration,
+    ) -> Result<(Arc<ClientQueue>, PollGuard), Box<Response>> {
+        let mut state = self.poll.lock().unwrap();
+        let Some(state) = self.poll_connection(&mut state, config) else {
+            return Err(Box::new(gone(None)));
+        };
+        if state.polling {
+            return Err(Box::new(
+                warp::reply::with_status("Another poll is already waiting", StatusCode::CONFLICT)
+                    .into_response(),
+            ));
+        }
+        state.polling = true;
+        Ok((state.queue.clone(), PollGuard(self.clone())))
+    }
+
+    fn end_poll(&self) {
+        let state = std::mem::replace(&mut *self.poll.lock().unwrap(), PollState::Ended);
+        if let PollState::Connected(state) = state {
+            state.queue.close();
+            self.disconnect();
+        }
+    }
+}
+
+/// Disconnect the session once it stops polling
+async fn watch_poll(session: Session, config: &'static Configuration) {
+    loop {
+        tokio::time::sleep(config.heartbeat_interval).await;
+        let expired = match &*session.poll.lock().unwrap() {
+            PollState::Connected(state) => {
+                !state.polling && state.last_seen.elapsed() > config.heartbeat_timeout
+            }
+            _ => return,
+        };
+        if expired {
+            info!("Long-polling client timed out");
+            return session.end_poll();
+        }
+    }
+}
+
+async fn get_session(registry: &'static RegistryInner, id: SessionID) -> Option<Session> {
+    registry.read().await.get(&id).cloned()
+}
+
+fn gone(reason: Option<DisconnectReason>) -> Response {
+    let message = reason.map_or("Disconnected", |reason| reason.message());
+    warp::reply::with_status(message, StatusCode::GONE).into_response()
+}
+
+/// Wait for messages and return them as a JSON array
+async fn poll(session: Session, config: &'static Configuration) -> Response {
+    let (queue, _guard) = match session.begin_poll(config) {
+        Ok(poll) => poll,
+        Err(response) => return *response,
+    };
+
+    let first = match tokio::time::timeout(config.heartbeat_interval, queue.pop()).await {
+        Ok(Some(message)) => Some(message),
+        Ok(None) => {
+            session.end_poll();
+            return gone(None);
+        }
+        Err(_) => None,
+    };
+
+    let mut body = b"[".to_vec();
+    for message in first.into_iter().chain(queue.take_ready()) {
+        match message {
+            ClientMessage::Payload(data, _) => {
+                if body.len() > 1 {
+                    body.push(b',');
+                }
+                body.extend(data);
+            }
+            ClientMessage::Close(reason) => {
+                session.end_poll();
+                return gone(Some(reason));
+            }
+        }
+    }
+    body.push(b']');
+
+    warp::http::Response::builder()
+        .header("content-type", "application/json")
+        .body(body.into())
+        .unwrap()
+}
+
+fn send(session: Session, config: &'static Configuration, body: &[u8]) -> Response {
+    if !session.touch_poll(config) {
+        return gone(None);
+    }
+    match serde_json::from_slice::<MsgRecv>(body) {
+        Ok(msg) => {
+            session.message(msg);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            info!("Received malformed message from client: {e}");
+            warp::reply::with_status(e.to_string(), StatusCode::BAD_REQUEST).into_response()
+        }
+    }
+}
+
+/// Create the routes for sending and receiving messages over plain HTTP
+pub(super) fn create_poll_filter(
+    registry: &'static RegistryInner,
+    config: &'static Configuration,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let receive = warp::path!("session" / SessionID / "poll")
+        .and(warp::get())
+        .and_then(move |id| async move {
+            let session = get_session(registry, id).await.ok_or_else(warp::reject)?;
+            Ok::<_, Rejection>(poll(session, config).await)
+        });
+
+    let send = warp::path!("session" / SessionID / "send")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_POLL_SEND_LENGTH))
+        .and(warp::body::bytes())
+        .and_then(move |id, body: warp::hyper::body::Bytes| async move {
+            let session = get_session(registry, id).await.ok_or_else(warp::reject)?;
+            Ok::<_, Rejection>(send(session, config, &body))
+        });
+
+    receive.or(send).unify()
+}
@@ -135,18 +135,38 @@ fn main() {
                 m::ClientID,
                 m::ItemID,
                 m::PathID,
+                m::ThreadID,
+                m::BoardID,
+                m::EndedPath,
+                m::FrameInfo,
                 m::LocationUpdate,
                 r::RejectLevel,
                 r::RejectMessage,
                 r::RejectReason,
+                m::batch::BatchCall,
+                m::batch::BatchResponse,
+                m::batch::NotifyCBatch,
 
                 c::Point,
                 c::Color,
                 c::Stroke,
+                c::Marker,
+                c::TextStyle,
+                c::TextAlign,
+                c::TextAnchor,
+                c::LineCap,
+                c::LineJoin,
+                c::Fill,
+                c::Gradient,
+                c::GradientStop,
                 c::Angle,
                 c::Transform,
                 c::SplineNode,
                 c::Spline,
+                c::RecognizedShape,
+                c::comment::CommentAnchor,
+                c::comment::Comment,
+                c::comment::CommentThread,
 
                 i::RectangleItem,
                 i::EllipseItem,
@@ -156,10 +176,17 @@ fn main() {
                 i::ImageItem,
                 i::TextItem,
                 i::LinkItem,
+                i::BoardLink,
+                i::LinkDestination,
                 i::TagItem,
+                i::ConnectorItem,
+                i::StickyNoteItem,
+                i::TableItem,
+                i::FrameItem,
                 i::Item,
 
                 virtual_whiteboard::tags::TagID,
+                virtual_whiteboard::upload::MediaInfo,
             ] with T =>format!("export {}", T::decl())
         }
     };
@@ -175,12 +202,27 @@ fn main() {
             SelectionRemoveItems,
             SelectionMove,
             EditSingleItem,
+            PatchSingleItem,
             DeleteItems,
             CreateItem,
+            ImportSvg,
+            TableInsertRow,
+            TableDeleteRow,
+            TableInsertColumn,
+            TableDeleteColumn,
+            TableEditCell,
+            CreateThread,
+            ReplyToThread,
+            ResolveThread,
+            ReopenThread,
             BeginPath,
             ContinuePath,
             EndPath,
             GetAllItemIDs,
+            GetItemsAtPoint,
+            GetFrames,
+            GetAllThreads,
+            FollowLink,
             GetAllClientIDs,
             GetClientState,
         ] with T => T::decl()}
@@ -202,9 +244,20 @@ fn main() {
             SelectionItemsRemoved,
             SelectionMoved,
             SingleItemEdited,
+            SingleItemPatched,
             ItemsDeleted,
             ItemCreated,
             PathStarted,
+            TableRowInserted,
+            TableRowDeleted,
+            TableColumnInserted,
+            TableColumnDeleted,
+            TableCellEdited,
+            ThreadCreated,
+            CommentAdded,
+            ThreadResolved,
+            ThreadReopened,
+            ThreadMoved,
         ] with T => T::decl())
     };
 
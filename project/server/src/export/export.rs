@@ -0,0 +1,294 @@
+//! Rendering boards into other formats
+
+pub mod pdf;
+pub mod png;
+pub mod svg;
+
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use resvg::usvg::{
+    self, fontdb, ImageHrefResolver, Options, PostProcessingSteps, Tree, TreeParsing, TreePostProc,
+};
+use serde::Deserialize;
+use warp::{filters::BoxedFilter, http::StatusCode, reject::Rejection, reply::Reply, Filter};
+
+use crate::{
+    canvas::{ActiveCanvas, Item, Rect},
+    message::ItemID,
+    upload::resolve_media,
+    GlobalRes,
+};
+
+/// Space left around the items when no region is specified
+const EXPORT_MARGIN: f64 = 1.0;
+
+/// Stops anything in an exported SVG from running if it is opened directly
+const SVG_CSP: &str = "default-src 'none'; img-src 'self' data:; style-src 'unsafe-inline'";
+
+lazy_static! {
+    static ref FONTS: fontdb::Database = {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        // Fall back to whatever is installed if the default sans-serif font is missing
+        let sans_serif = fontdb::Query {
+            families: &[fontdb::Family::SansSerif],
+            ..Default::default()
+        };
+        if db.query(&sans_serif).is_none() {
+            let family = db
+                .faces()
+                .find_map(|face| face.families.first())
+                .map(|(name, _)| name.clone());
+            if let Some(family) = family {
+                db.set_sans_serif_family(family);
+            }
+        }
+        db
+    };
+}
+
+/// Query parameters selecting what part of a board to export
+#[derive(Deserialize, Debug, Default, clap::Args)]
+pub struct ExportOptions {
+    /// The left edge of the region to export
+    #[arg(long, allow_hyphen_values = true)]
+    pub x: Option<f64>,
+    /// The top edge of the region to export
+    #[arg(long, allow_hyphen_values = true)]
+    pub y: Option<f64>,
+    /// The width of the region to export
+    #[arg(long)]
+    pub width: Option<f64>,
+    /// The height of the region to export
+    #[arg(long)]
+    pub height: Option<f64>,
+    /// A comma-separated list of item IDs to include
+    #[arg(long)]
+    pub ids: Option<String>,
+    /// The ID of a frame to export, along with the items inside it.
+    /// The frame's area is exported unless a region is specified
+    #[arg(long)]
+    pub frame: Option<u32>,
+}
+
+impl ExportOptions {
+    /// Check that any part of the region given is finite, with a positive size
+    pub fn validate(&self) -> Result<(), String> {
+        let position = [self.x, self.y];
+        let size = [self.width, self.height];
+        if position
+            .iter()
+            .chain(&size)
+            .flatten()
+            .any(|v| !v.is_finite())
+        {
+            return Err("The region must be finite".to_string());
+        }
+        if size.iter().flatten().any(|&v| v <= 0.0) {
+            return Err("The region must have a positive width and height".to_string());
+        }
+        Ok(())
+    }
+
+    /// The region to export, if one was fully specified
+    pub fn region(&self) -> Option<Rect> {
+        Some(Rect::from_size(self.x?, self.y?, self.width?, self.height?))
+    }
+
+    /// The items to export, if limited
+    pub fn ids(&self) -> Option<Vec<ItemID>> {
+        let ids = self.ids.as_ref()?;
+        Some(
+            ids.split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .map(ItemID)
+                .collect(),
+        )
+    }
+}
+
+/// Collect the items selected by the options, in the order they were created
+pub async fn collect_items(canvas: &ActiveCanvas, options: &ExportOptions) -> Vec<(ItemID, Item)> {
+    let ids = options.ids();
+    let frame = match options.frame.map(ItemID) {
+        Some(id) => match canvas.get_item(id).await {
+            Some(Item::Frame(frame)) => Some((id, frame)),
+            _ => return Vec::new(),
+        },
+        None => None,
+    };
+    let mut items = Vec::new();
+    canvas
+        .scan_items(|id, item| {
+            let in_frame = frame
+                .as_ref()
+                .is_none_or(|(frame_id, frame)| id == *frame_id || frame.contains(item));
+            if in_frame && ids.as_ref().is_none_or(|ids| ids.contains(&id)) {
+                items.push((id, item.clone()));
+            }
+        })
+        .await;
+    items.sort_by_key(|&(id, _)| id);
+    items
+}
+
+/// The area to export: either the requested region, the requested frame or the extent of all the items
+pub fn view_box(items: &[(ItemID, Item)], options: &ExportOptions) -> Rect {
+    let frame = || {
+        let frame = ItemID(options.frame?);
+        let (_, item) = items.iter().find(|(id, _)| *id == frame)?;
+        Some(item.bounds())
+    };
+    options.region().or_else(frame).unwrap_or_else(|| {
+        items
+            .iter()
+            .map(|(_, item)| item.bounds())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Rect::from_size(0.0, 0.0, 0.0, 0.0))
+            .expand(EXPORT_MARGIN)
+    })
+}
+
+/// Render the selected part of a canvas as an SVG document
+pub async fn render_svg(canvas: &ActiveCanvas, options: &ExportOptions) -> String {
+    let items = collect_items(canvas, options).await;
+    let view = view_box(&items, options);
+    svg::render_document(items.iter().map(|(id, item)| (*id, item)), view)
+}
+
+/// Resolve image references against the media directory instead of the working directory
+fn media_resolver(media_root: PathBuf) -> ImageHrefResolver {
+    let load_file = ImageHrefResolver::default_string_resolver();
+    ImageHrefResolver {
+        resolve_string: Box::new(move |href, opts| {
+            let path = resolve_media(&media_root, href)?;
+            load_file(path.to_str()?, opts)
+        }),
+        ..Default::default()
+    }
+}
+
+/// Parse an SVG document produced by [`render_svg`], loading images from `media_root`
+pub fn parse_tree(svg: &str, media_root: PathBuf) -> Result<Tree, usvg::Error> {
+    let options = Options {
+        font_family: "sans-serif".to_string(),
+        image_href_resolver: media_resolver(media_root),
+        ..Default::default()
+    };
+    let mut tree = Tree::from_str(svg, &options)?;
+    tree.postprocess(PostProcessingSteps::default(), &FONTS);
+    Ok(tree)
+}
+
+fn bad_request(msg: String) -> warp::reply::Response {
+    warp::reply::with_status(msg, StatusCode::BAD_REQUEST).into_response()
+}
+
+/// Create a filter for exporting boards
+pub fn create_export_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
+    let svg_filter = warp::path!("export" / String / "svg")
+        .and(warp::get())
+        .and(warp::query::<ExportOptions>())
+        .and_then(move |name: String, options: ExportOptions| async move {
+            if let Err(msg) = options.validate() {
+                return Ok(bad_request(msg));
+            }
+            let canvas = res
+                .boards
+                .get_canvas(&name)
+                .await
+                .ok_or_else(warp::reject::not_found)?;
+            let svg = render_svg(&canvas, &options).await;
+            let reply = warp::reply::with_header(svg, "content-type", "image/svg+xml");
+            let reply = warp::reply::with_header(reply, "content-security-policy", SVG_CSP);
+            Ok::<_, Rejection>(reply.into_response())
+        });
+
+    let png_filter = warp::path!("export" / String / "png")
+        .and(warp::get())
+        .and(warp::query::<ExportOptions>())
+        .and(warp::query::<png::PngOptions>())
+        .and_then(
+            move |name: String, options: ExportOptions, png_options| async move {
+                if let Err(msg) = options.validate() {
+                    return Ok(bad_request(msg));
+                }
+                let canvas = res
+                    .boards
+                    .get_canvas(&name)
+                    .await
+                    .ok_or_else(warp::reject::not_found)?;
+                let media_root = res.config.media_root.clone();
+                let reply = match png::render_png(&canvas, &options, png_options, media_root).await
+                {
+                    Ok(png) => {
+                        warp::reply::with_header(png, "content-type", "image/png").into_response()
+                    }
+                    Err(e @ png::PngError::InvalidSize) => {
+                        warp::reply::with_status(e.to_string(), StatusCode::BAD_REQUEST)
+                            .into_response()
+                    }
+                    Err(e) => {
+                        warp::reply::with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+                            .into_response()
+                    }
+                };
+                Ok::<_, Rejection>(reply)
+            },
+        );
+
+    let pdf_filter = warp::path!("export" / String / "pdf")
+        .and(warp::get())
+        .and(warp::query::<ExportOptions>())
+        .and(warp::query::<pdf::PdfOptions>())
+        .and_then(
+            move |name: String, options: ExportOptions, pdf_options| async move {
+                if let Err(msg) = options.validate() {
+                    return Ok(bad_request(msg));
+                }
+                let canvas = res
+                    .boards
+                    .get_canvas(&name)
+                    .await
+                    .ok_or_else(warp::reject::not_found)?;
+                let media_root = res.config.media_root.clone();
+                let reply = match pdf::render_pdf(&canvas, &options, &pdf_options, media_root).await
+                {
+                    Ok(pdf) => warp::reply::with_header(pdf, "content-type", "application/pdf")
+                        .into_response(),
+                    Err(e @ (pdf::PdfError::InvalidRegion(_) | pdf::PdfError::TooManyPages)) => {
+                        warp::reply::with_status(e.to_string(), StatusCode::BAD_REQUEST)
+                            .into_response()
+                    }
+                    Err(e) => {
+                        warp::reply::with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+                            .into_response()
+                    }
+                };
+                Ok::<_, Rejection>(reply)
+            },
+        );
+
+    let thumbnail_filter = warp::path!("thumbnail" / String).and(warp::get()).and_then(
+        move |name: String| async move {
+            let png = res
+                .boards
+                .get_thumbnail(&name)
+                .await
+                .ok_or_else(warp::reject::not_found)?;
+            Ok::<_, Rejection>(
+                warp::reply::with_header(png, "content-type", "image/png").into_response(),
+            )
+        },
+    );
+
+    svg_filter
+        .or(png_filter)
+        .unify()
+        .or(pdf_filter)
+        .unify()
+        .or(thumbnail_filter)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,200 @@
+//! Converting boards into multi-page PDF documents
+
+use std::{fmt, path::PathBuf};
+
+use pdf_writer::{Content, Finish, Name, Pdf, Ref};
+use resvg::usvg;
+use serde::Deserialize;
+
+use super::{collect_items, parse_tree, svg, view_box, ExportOptions};
+use crate::canvas::{ActiveCanvas, Rect};
+
+/// The most pages that will be produced for one document
+const MAX_PDF_PAGES: usize = 64;
+
+/// PDF points per pixel of the exported SVG, which is drawn at 96 DPI like the client
+const PT_PER_PX: f32 = 72.0 / 96.0;
+
+/// The name each page uses for its content
+const PAGE_CONTENT: Name<'static> = Name(b"Board");
+
+/// Query parameters controlling how a board is split into pages
+#[derive(Deserialize, Debug, Default, clap::Args)]
+pub struct PdfOptions {
+    /// Areas to render as separate pages, as `x,y,width,height` separated by `;`
+    #[arg(long, allow_hyphen_values = true)]
+    pub regions: Option<String>,
+}
+
+impl PdfOptions {
+    /// The regions to render as pages, if any were specified
+    pub fn regions(&self) -> Result<Option<Vec<Rect>>, PdfError> {
+        let Some(regions) = &self.regions else {
+            return Ok(None);
+        };
+        let regions = regions
+            .split(';')
+            .map(|region| {
+                let values = region
+                    .split(',')
+                    .map(|v| v.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| PdfError::InvalidRegion(region.to_string()))?;
+                match values[..] {
+                    [x, y, width, height]
+                        if [x, y, width, height].iter().all(|v| v.is_finite())
+                            && width > 0.0
+                            && height > 0.0 =>
+                    {
+                        Ok(Rect::from_size(x, y, width, height))
+                    }
+                    _ => Err(PdfError::InvalidRegion(region.to_string())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if regions.len() > MAX_PDF_PAGES {
+            return Err(PdfError::TooManyPages);
+        }
+        Ok(Some(regions))
+    }
+}
+
+/// Reasons a board could not be converted to a PDF
+#[derive(Debug)]
+pub enum PdfError {
+    /// A region was not four finite numbers with a positive size
+    InvalidRegion(String),
+    /// More than [`MAX_PDF_PAGES`] regions were requested
+    TooManyPages,
+    /// The generated SVG could not be parsed
+    Parse(usvg::Error),
+    /// The conversion task did not complete
+    Cancelled,
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRegion(region) => write!(f, "Invalid region: {region:?}"),
+            Self::TooManyPages => write!(f, "A document can have at most {MAX_PDF_PAGES} pages"),
+            Self::Parse(e) => write!(f, "Failed to parse exported SVG: {e}"),
+            Self::Cancelled => write!(f, "The conversion task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PdfError {}
+
+/// Assemble SVG documents produced by [`svg::render_document`] into a PDF with one page each
+pub fn write_pdf(pages: &[String], media_root: PathBuf) -> Result<Vec<u8>, PdfError> {
+    let mut next_ref = Ref::new(1);
+    let catalog_ref = next_ref.bump();
+    let page_tree_ref = next_ref.bump();
+
+    let mut pdf = Pdf::new();
+    let mut page_refs = Vec::with_capacity(pages.len());
+
+    for page_svg in pages {
+        let tree = parse_tree(page_svg, media_root.clone()).map_err(PdfError::Parse)?;
+        let page_ref = next_ref.bump();
+        let content_ref = next_ref.bump();
+        let board_ref = next_ref.bump();
+        next_ref = svg2pdf::convert_tree_into(&tree, Default::default(), &mut pdf, board_ref);
+
+        // The converted board is a unit square, so stretch it over the page
+        let width = tree.size.width() * PT_PER_PX;
+        let height = tree.size.height() * PT_PER_PX;
+        let mut content = Content::new();
+        content
+            .transform([width, 0.0, 0.0, height, 0.0, 0.0])
+            .x_object(PAGE_CONTENT);
+        pdf.stream(content_ref, &content.finish());
+
+        let mut page = pdf.page(page_ref);
+        page.media_box(pdf_writer::Rect::new(0.0, 0.0, width, height))
+            .parent(page_tree_ref)
+            .contents(content_ref);
+        page.resources().x_objects().pair(PAGE_CONTENT, board_ref);
+        page.finish();
+
+        page_refs.push(page_ref);
+    }
+
+    pdf.catalog(catalog_ref).pages(page_tree_ref);
+    pdf.pages(page_tree_ref)
+        .count(page_refs.len() as i32)
+        .kids(page_refs);
+    Ok(pdf.finish())
+}
+
+/// Render a canvas as a PDF, either as a single page or one page per requested region
+pub async fn render_pdf(
+    canvas: &ActiveCanvas,
+    options: &ExportOptions,
+    pdf_options: &PdfOptions,
+    media_root: PathBuf,
+) -> Result<Vec<u8>, PdfError> {
+    let regions = pdf_options.regions()?;
+    let items = collect_items(canvas, options).await;
+    let views = regions.unwrap_or_else(|| vec![view_box(&items, options)]);
+    let pages = views
+        .into_iter()
+        .map(|view| svg::render_document(items.iter().map(|(id, item)| (*id, item)), view))
+        .collect::<Vec<_>>();
+    tokio::task::spawn_blocking(move || write_pdf(&pages, media_root))
+        .await
+        .map_err(|_| PdfError::Cancelled)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(s: &str) -> Result<Option<Vec<Rect>>, PdfError> {
+        PdfOptions {
+            regions: Some(s.to_string()),
+        }
+        .regions()
+    }
+
+    #[test]
+    fn no_regions() {
+        assert!(matches!(PdfOptions::default().regions(), Ok(None)));
+    }
+
+    #[test]
+    fn parses_each_region() {
+        let regions = regions("0,0,2,1; -1.5, 2, 3, 4").unwrap().unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].width(), regions[0].height()), (2.0, 1.0));
+        assert_eq!((regions[1].min.x, regions[1].min.y), (-1.5, 2.0));
+        assert_eq!((regions[1].width(), regions[1].height()), (3.0, 4.0));
+    }
+
+    #[test]
+    fn rejects_malformed_regions() {
+        for region in [
+            "",
+            "1,2,3",
+            "1,2,3,4,5",
+            "a,0,1,1",
+            "0,0,0,1",
+            "0,0,1,-1",
+            "0,0,inf,1",
+            "NaN,0,1,1",
+        ] {
+            assert!(
+                matches!(regions(region), Err(PdfError::InvalidRegion(_))),
+                "{region:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn limits_page_count() {
+        let many = vec!["0,0,1,1"; MAX_PDF_PAGES + 1].join(";");
+        assert!(matches!(regions(&many), Err(PdfError::TooManyPages)));
+        let most = vec!["0,0,1,1"; MAX_PDF_PAGES].join(";");
+        assert_eq!(regions(&most).unwrap().unwrap().len(), MAX_PDF_PAGES);
+    }
+}
@@ -0,0 +1,224 @@
+//! Rasterising exported SVG documents on the CPU
+
+use std::{fmt, path::PathBuf};
+
+use resvg::tiny_skia::{Pixmap, Transform};
+use serde::Deserialize;
+
+use super::{collect_items, parse_tree, render_svg, svg, view_box, ExportOptions};
+use crate::canvas::{ActiveCanvas, PX_PER_UNIT};
+
+/// The largest width or height (in pixels) of an image that will be rendered
+const MAX_PNG_DIMENSION: u32 = 16384;
+
+/// The largest number of pixels in an image that will be rendered (32 megapixels)
+const MAX_PNG_PIXELS: u64 = 32 * 1024 * 1024;
+
+/// The largest width or height (in pixels) of a board thumbnail
+const THUMBNAIL_SIZE: f64 = 256.0;
+
+/// Query parameters controlling rasterisation
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct PngOptions {
+    /// The number of pixels per board unit
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    PX_PER_UNIT
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            scale: default_scale(),
+        }
+    }
+}
+
+/// Reasons a board could not be rendered to a PNG
+#[derive(Debug)]
+pub enum PngError {
+    /// The generated SVG could not be parsed
+    Parse(resvg::usvg::Error),
+    /// The requested image was empty or too large
+    InvalidSize,
+    /// The image could not be encoded
+    Encode(String),
+    /// The rendering task did not complete
+    Cancelled,
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "Failed to parse exported SVG: {e}"),
+            Self::InvalidSize => write!(
+                f,
+                "The image must be between 1 and {MAX_PNG_DIMENSION} pixels in each dimension \
+                 and at most {MAX_PNG_PIXELS} pixels in total"
+            ),
+            Self::Encode(e) => write!(f, "Failed to encode PNG: {e}"),
+            Self::Cancelled => write!(f, "The rendering task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PngError {}
+
+/// Rasterise an SVG document produced by [`render_svg`] at `scale` pixels per unit
+pub fn rasterize(svg: &str, scale: f64, media_root: PathBuf) -> Result<Vec<u8>, PngError> {
+    let tree = parse_tree(svg, media_root).map_err(PngError::Parse)?;
+
+    // The document is sized at the client's resolution, so rescale from that
+    let factor = (scale / PX_PER_UNIT) as f32;
+    let dimension = |size: f32| {
+        let size = (size * factor).ceil();
+        (size >= 1.0 && size <= MAX_PNG_DIMENSION as f32).then_some(size as u32)
+    };
+    let width = dimension(tree.size.width()).ok_or(PngError::InvalidSize)?;
+    let height = dimension(tree.size.height()).ok_or(PngError::InvalidSize)?;
+    if u64::from(width) * u64::from(height) > MAX_PNG_PIXELS {
+        return Err(PngError::InvalidSize);
+    }
+
+    let mut pixmap = Pixmap::new(width, height).ok_or(PngError::InvalidSize)?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(factor, factor),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .encode_png()
+        .map_err(|e| PngError::Encode(e.to_string()))
+}
+
+/// Render the selected part of a canvas as a PNG image
+pub async fn render_png(
+    canvas: &ActiveCanvas,
+    options: &ExportOptions,
+    png_options: PngOptions,
+    media_root: PathBuf,
+) -> Result<Vec<u8>, PngError> {
+    let svg = render_svg(canvas, options).await;
+    tokio::task::spawn_blocking(move || rasterize(&svg, png_options.scale, media_root))
+        .await
+        .map_err(|_| PngError::Cancelled)?
+}
+
+/// Render a small preview of a whole canvas, fitted within [`THUMBNAIL_SIZE`] pixels
+pub async fn render_thumbnail(
+    canvas: &ActiveCanvas,
+    media_root: PathBuf,
+) -> Result<Vec<u8>, PngError> {
+    let options = ExportOptions::default();
+    let items = collect_items(canvas, &options).await;
+    let view = view_box(&items, &options);
+    // Never enlarge small boards beyond the client's own resolution
+    let scale = (THUMBNAIL_SIZE / view.width())
+        .min(THUMBNAIL_SIZE / view.height())
+        .min(PX_PER_UNIT);
+    let svg = svg::render_document(items.iter().map(|(id, item)| (*id, item)), view);
+    tokio::task::spawn_blocking(move || rasterize(&svg, scale, media_root))
+        .await
+        .map_err(|_| PngError::Cancelled)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Item;
+
+    /// An empty document of the given size, which is also its size in pixels at [`PX_PER_UNIT`]
+    fn document(width: u32, height: u32) -> String {
+        format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"/>"#)
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn rectangle(size: f64) -> Item {
+        serde_json::from_value(serde_json::json!({
+            "type": "Rectangle",
+            "transform": {
+                "origin": { "x": 0.0, "y": 0.0 },
+                "basisX": { "x": size, "y": 0.0 },
+                "basisY": { "x": 0.0, "y": size },
+            },
+            "stroke": { "width": 0.1, "color": "#000000ff" },
+            "fill": "#ffffffff",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rasterize_limits_dimensions() {
+        let widest = document(MAX_PNG_DIMENSION, 1);
+        assert!(rasterize(&widest, PX_PER_UNIT, PathBuf::new()).is_ok());
+        for (width, height) in [(MAX_PNG_DIMENSION + 1, 1), (1, MAX_PNG_DIMENSION + 1)] {
+            assert!(matches!(
+                rasterize(&document(width, height), PX_PER_UNIT, PathBuf::new()),
+                Err(PngError::InvalidSize)
+            ));
+        }
+    }
+
+    #[test]
+    fn rasterize_limits_pixels() {
+        // Within the limit in each dimension, but not in total
+        let side = (MAX_PNG_PIXELS as f64).sqrt() as u32 + 1;
+        assert!(side <= MAX_PNG_DIMENSION);
+        assert!(matches!(
+            rasterize(&document(side, side), PX_PER_UNIT, PathBuf::new()),
+            Err(PngError::InvalidSize)
+        ));
+    }
+
+    #[test]
+    fn rasterize_rejects_invalid_scales() {
+        for scale in [f64::NAN, 0.0, -1.0, -PX_PER_UNIT, f64::INFINITY] {
+            assert!(
+                matches!(
+                    rasterize(&document(10, 10), scale, PathBuf::new()),
+                    Err(PngError::InvalidSize)
+                ),
+                "{scale} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn render_png_rejects_invalid_scales() {
+        let canvas = ActiveCanvas::new_empty();
+        block_on(canvas.add_item(rectangle(1.0)));
+        let render = |scale| {
+            block_on(render_png(
+                &canvas,
+                &ExportOptions::default(),
+                PngOptions { scale },
+                PathBuf::new(),
+            ))
+        };
+        assert!(render(PX_PER_UNIT).is_ok());
+        for scale in [f64::NAN, 0.0, -1.0, 1e9] {
+            assert!(
+                matches!(render(scale), Err(PngError::InvalidSize)),
+                "{scale} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn thumbnails_of_large_boards_stay_small() {
+        let canvas = ActiveCanvas::new_empty();
+        block_on(canvas.add_item(rectangle(1e6)));
+        let png = block_on(render_thumbnail(&canvas, PathBuf::new())).unwrap();
+        let size = imagesize::blob_size(&png).unwrap();
+        assert!(size.width <= THUMBNAIL_SIZE as usize && size.height <= THUMBNAIL_SIZE as usize);
+    }
+}
@@ -0,0 +1,592 @@
+//! Translation of items into SVG
+//!
+//! The output mirrors how the client draws each item, so that exports look the same as the board
+
+use std::{borrow::Cow, fmt::Write};
+
+use crate::{
+    canvas::{
+        item::{
+            ConnectorItem, EllipseItem, FrameItem, ImageItem, LineItem, LinkItem, PathItem,
+            PolygonItem, RectangleItem, StickyNoteItem, TableItem, TagItem, TextItem,
+        },
+        Color, Fill, Gradient, Item, LineCap, LineJoin, Marker, Point, Rect, Spline, Stroke,
+        TextAlign, TextAnchor, Transform, FONT_SIZE, PX_PER_UNIT,
+    },
+    message::ItemID,
+};
+
+/// The colour of frame outlines and names
+const FRAME_COLOR: &str = "#888888";
+
+/// The width of frame outlines, which doesn't change with the size of the frame
+const FRAME_WIDTH: f64 = 0.02;
+
+/// Escape a string for use in an attribute or text node
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Whether a link can be followed from an exported document, only allowing web and relative URLs
+fn is_safe_href(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme, so they are ignored here too
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(idx) if url[idx..].starts_with(':') => {
+            let scheme = url[..idx].to_ascii_lowercase();
+            scheme == "http" || scheme == "https"
+        }
+        _ => true,
+    }
+}
+
+fn transform_attr(t: &Transform) -> String {
+    format!(
+        r#"transform="matrix({} {} {} {} {} {})""#,
+        t.basis_x.x, t.basis_x.y, t.basis_y.x, t.basis_y.y, t.origin.x, t.origin.y
+    )
+}
+
+fn stroke_attrs(stroke: &Stroke) -> String {
+    let mut attrs = format!(
+        r#"stroke="{}" stroke-width="{}""#,
+        escape(stroke.color.as_str()),
+        stroke.width
+    );
+    if !stroke.dash.is_empty() {
+        let dash: Vec<_> = stroke.dash.iter().map(f64::to_string).collect();
+        let _ = write!(attrs, r#" stroke-dasharray="{}""#, dash.join(" "));
+    }
+    let cap = match stroke.cap {
+        LineCap::Butt => None,
+        LineCap::Round => Some("round"),
+        LineCap::Square => Some("square"),
+    };
+    if let Some(cap) = cap {
+        let _ = write!(attrs, r#" stroke-linecap="{cap}""#);
+    }
+    let join = match stroke.join {
+        LineJoin::Miter => None,
+        LineJoin::Round => Some("round"),
+        LineJoin::Bevel => Some("bevel"),
+    };
+    if let Some(join) = join {
+        let _ = write!(attrs, r#" stroke-linejoin="{join}""#);
+    }
+    if stroke.opacity != 1.0 {
+        let _ = write!(attrs, r#" stroke-opacity="{}""#, stroke.opacity);
+    }
+    attrs
+}
+
+fn fill_attr(fill: &Color) -> String {
+    format!(r#"fill="{}""#, escape(fill.as_str()))
+}
+
+/// The definition of a gradient used by an item, if its fill has one
+fn gradient_def(id: ItemID, fill: &Fill) -> String {
+    let Fill::Gradient(gradient) = fill else {
+        return String::new();
+    };
+    let (mut out, stops, tag) = match gradient {
+        Gradient::Linear { start, end, stops } => (
+            format!(
+                r#"<linearGradient id="fill-{}" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+                *id, start.x, start.y, end.x, end.y
+            ),
+            stops,
+            "linearGradient",
+        ),
+        Gradient::Radial {
+            center,
+            radius,
+            stops,
+        } => (
+            format!(
+                r#"<radialGradient id="fill-{}" cx="{}" cy="{}" r="{radius}">"#,
+                *id, center.x, center.y
+            ),
+            stops,
+            "radialGradient",
+        ),
+    };
+    for stop in stops {
+        let _ = write!(
+            out,
+            r#"<stop offset="{}" stop-color="{}"/>"#,
+            stop.offset,
+            escape(stop.color.as_str())
+        );
+    }
+    format!("<defs>{out}</{tag}></defs>")
+}
+
+/// The `fill` attribute of an item's shape, referring to [`gradient_def`] for gradients
+fn paint_attr(id: ItemID, fill: &Fill) -> String {
+    match fill {
+        Fill::Solid(color) => fill_attr(color),
+        Fill::Gradient(_) => format!(r#"fill="url(#fill-{})""#, *id),
+    }
+}
+
+/// The `d` attribute of a [`Spline`], built the same way as the client's path helper
+pub fn spline_path(spline: &Spline) -> String {
+    let Some((first, rest)) = spline.points.split_first() else {
+        return String::new();
+    };
+    let mut d = format!(
+        "M {} {} c 0 0 0 0 0 0 S",
+        first.position.x, first.position.y
+    );
+    for node in rest {
+        let _ = write!(
+            d,
+            " {} {} {} {}",
+            node.position.x - node.velocity.x,
+            node.position.y - node.velocity.y,
+            node.position.x,
+            node.position.y
+        );
+    }
+    d
+}
+
+/// The point `distance` back from `tip` towards `from`
+fn step_back(from: Point, tip: Point, distance: f64) -> Point {
+    let len = (tip.x - from.x).hypot(tip.y - from.y);
+    if len == 0.0 {
+        return tip;
+    }
+    let t = distance.min(len) / len;
+    Point::new(tip.x + (from.x - tip.x) * t, tip.y + (from.y - tip.y) * t)
+}
+
+/// A marker at `tip` pointing away from `from`, and where the line itself should stop
+fn marker(marker: Marker, from: Point, tip: Point, stroke: &Stroke) -> (String, Point) {
+    let size = marker.size(stroke.width);
+    let base = step_back(from, tip, size);
+    let middle = Point::new((base.x + tip.x) / 2.0, (base.y + tip.y) / 2.0);
+    // Half of the marker's width, across the line
+    let normal = Point::new((base.y - tip.y) / 2.0, (tip.x - base.x) / 2.0);
+    let side = |p: Point, s: f64| Point::new(p.x + normal.x * s, p.y + normal.y * s);
+    let points = |points: &[Point]| {
+        let points: Vec<_> = points.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+        points.join(" ")
+    };
+    let filled = |shape: &[Point]| {
+        format!(
+            r#"<polygon points="{}" {} fill-opacity="{}"/>"#,
+            points(shape),
+            fill_attr(&stroke.color),
+            stroke.opacity
+        )
+    };
+    // Dashes would break up the marker, so its strokes are always solid
+    let stroked = |shape: &[Point]| {
+        let solid = Stroke {
+            dash: Vec::new(),
+            ..stroke.clone()
+        };
+        format!(
+            r#"<polyline points="{}" fill="none" {}/>"#,
+            points(shape),
+            stroke_attrs(&solid)
+        )
+    };
+    match marker {
+        Marker::None => (String::new(), tip),
+        Marker::Arrow => (filled(&[tip, side(base, 1.0), side(base, -1.0)]), base),
+        Marker::OpenArrow => (stroked(&[side(base, 1.0), tip, side(base, -1.0)]), tip),
+        Marker::Circle => {
+            let svg = format!(
+                r#"<circle cx="{}" cy="{}" r="{}" {} fill-opacity="{}"/>"#,
+                middle.x,
+                middle.y,
+                size / 2.0,
+                fill_attr(&stroke.color),
+                stroke.opacity
+            );
+            (svg, middle)
+        }
+        Marker::Diamond => (
+            filled(&[tip, side(middle, 1.0), base, side(middle, -1.0)]),
+            base,
+        ),
+        Marker::Bar => (stroked(&[side(tip, 1.0), side(tip, -1.0)]), tip),
+    }
+}
+
+/// A straight line with a marker at each end
+fn marked_line(
+    start: Point,
+    end: Point,
+    stroke: &Stroke,
+    start_marker: Marker,
+    end_marker: Marker,
+) -> String {
+    let (start_svg, line_start) = marker(start_marker, end, start, stroke);
+    let (end_svg, line_end) = marker(end_marker, start, end, stroke);
+    format!(
+        r#"<g><line x1="{}" y1="{}" x2="{}" y2="{}" {}/>{start_svg}{end_svg}</g>"#,
+        line_start.x,
+        line_start.y,
+        line_end.x,
+        line_end.y,
+        stroke_attrs(stroke)
+    )
+}
+
+/// Lines of text centred on the origin
+fn text_lines(lines: &[&str], font_size: f64, attrs: &str) -> String {
+    aligned_text_lines(
+        lines,
+        font_size,
+        TextAlign::Center,
+        TextAnchor::Middle,
+        attrs,
+    )
+}
+
+/// Lines of text placed around the origin as described by [`TextStyle`]
+fn aligned_text_lines(
+    lines: &[&str],
+    font_size: f64,
+    align: TextAlign,
+    anchor: TextAnchor,
+    attrs: &str,
+) -> String {
+    let text_anchor = match align {
+        TextAlign::Left => "start",
+        TextAlign::Center => "middle",
+        TextAlign::Right => "end",
+    };
+    // Each line is 1.2em high and drawn around its middle
+    let height = lines.len() as f64 * 1.2;
+    let offset = match anchor {
+        TextAnchor::Top => 0.6,
+        TextAnchor::Middle => 0.6 - height / 2.0,
+        TextAnchor::Bottom => 0.6 - height,
+    };
+    let mut out = format!(
+        r#"<text text-anchor="{text_anchor}" dominant-baseline="central" font-size="{font_size}" {attrs}>"#
+    );
+    for (idx, line) in lines.iter().enumerate() {
+        let dy = if idx == 0 { offset } else { 1.2 };
+        let _ = write!(out, r#"<tspan x="0" dy="{dy}em">{}</tspan>"#, escape(line));
+    }
+    out.push_str("</text>");
+    out
+}
+
+/// The directions of a transform's axes, without any stretching
+fn unscaled(t: &Transform) -> Transform {
+    let unit = |v: Point| {
+        let len = v.x.hypot(v.y);
+        if len == 0.0 {
+            v
+        } else {
+            Point::new(v.x / len, v.y / len)
+        }
+    };
+    Transform {
+        origin: t.origin,
+        basis_x: unit(t.basis_x),
+        basis_y: unit(t.basis_y),
+    }
+}
+
+/// Wrap pixel-sized content so that it is placed by a [`Transform`] like the client does
+fn pixel_group(t: &Transform, content: &str) -> String {
+    format!(
+        r#"<g {}><g transform="scale({})">{content}</g></g>"#,
+        transform_attr(t),
+        1.0 / PX_PER_UNIT
+    )
+}
+
+/// Translate a single item into an SVG element
+pub fn item_to_svg(id: ItemID, item: &Item) -> String {
+    match item {
+        Item::Rectangle(RectangleItem {
+            transform,
+            stroke,
+            fill,
+        }) => format!(
+            r#"{}<rect x="-0.5" y="-0.5" width="1" height="1" {} {} {}/>"#,
+            gradient_def(id, fill),
+            transform_attr(transform),
+            stroke_attrs(stroke),
+            paint_attr(id, fill)
+        ),
+        Item::Ellipse(EllipseItem {
+            transform,
+            stroke,
+            fill,
+        }) => format!(
+            r#"{}<circle r="0.5" {} {} {}/>"#,
+            gradient_def(id, fill),
+            transform_attr(transform),
+            stroke_attrs(stroke),
+            paint_attr(id, fill)
+        ),
+        Item::Line(LineItem {
+            start,
+            end,
+            stroke,
+            start_marker,
+            end_marker,
+        }) => marked_line(*start, *end, stroke, *start_marker, *end_marker),
+        Item::Polygon(PolygonItem {
+            points,
+            stroke,
+            fill,
+        }) => {
+            let points = points
+                .iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!(
+                r#"{}<polygon points="{points}" {} {}/>"#,
+                gradient_def(id, fill),
+                stroke_attrs(stroke),
+                paint_attr(id, fill)
+            )
+        }
+        Item::Path(PathItem {
+            transform,
+            path,
+            stroke,
+            start_marker,
+            end_marker,
+        }) => {
+            // The curve isn't shortened to make room for the markers, they are drawn over its ends
+            let mut markers = String::new();
+            if let Some([(start_from, start), (end_from, end)]) = path.ends() {
+                markers.push_str(&marker(*start_marker, start_from, start, stroke).0);
+                markers.push_str(&marker(*end_marker, end_from, end, stroke).0);
+            }
+            format!(
+                r#"<g {}><path d="{}" fill="none" {}/>{markers}</g>"#,
+                transform_attr(transform),
+                spline_path(path),
+                stroke_attrs(stroke)
+            )
+        }
+        Item::Image(ImageItem {
+            transform,
+            url,
+            description,
+            crop,
+            ..
+        }) => {
+            let image = format!(
+                r#"<image href="{}"><title>{}</title></image>"#,
+                escape(url),
+                escape(description)
+            );
+            let content = match crop {
+                Some(crop) => format!(
+                    r#"<svg width="{w}" height="{h}" viewBox="{} {} {w} {h}">{image}</svg>"#,
+                    crop.min.x,
+                    crop.min.y,
+                    w = crop.width(),
+                    h = crop.height(),
+                ),
+                None => image,
+            };
+            pixel_group(transform, &content)
+        }
+        Item::Text(TextItem {
+            transform,
+            text,
+            style,
+        }) => {
+            let lines: Vec<_> = text.split('\n').collect();
+            let attrs = format!(
+                r#"font-family="{}" {}"#,
+                escape(&style.font_family),
+                fill_attr(&style.color)
+            );
+            pixel_group(
+                transform,
+                &aligned_text_lines(&lines, style.font_size, style.align, style.anchor, &attrs),
+            )
+        }
+        Item::Link(link) => {
+            let LinkItem { transform, url, .. } = link;
+            let label = text_lines(
+                &[link.label()],
+                FONT_SIZE,
+                r#"fill="blue" text-decoration="underline""#,
+            );
+            let content = if is_safe_href(url) {
+                format!(r#"<a href="{}">{label}</a>"#, escape(url))
+            } else {
+                label
+            };
+            pixel_group(transform, &content)
+        }
+        Item::Tag(TagItem {
+            transform, data, ..
+        }) => pixel_group(
+            transform,
+            &text_lines(&[data], FONT_SIZE, r#"font-style="italic""#),
+        ),
+        Item::StickyNote(note) => {
+            let StickyNoteItem {
+                transform,
+                background,
+                text,
+                ..
+            } = note;
+            // The text keeps its proportions however the note is stretched
+            let text_transform = unscaled(transform);
+            let lines: Vec<_> = text.split('\n').collect();
+            format!(
+                r#"<g><rect x="-0.5" y="-0.5" width="1" height="1" {} {}/>{}</g>"#,
+                transform_attr(transform),
+                fill_attr(background),
+                pixel_group(&text_transform, &text_lines(&lines, note.font_size(), ""))
+            )
+        }
+        Item::Frame(FrameItem { transform, name }) => {
+            let corners: Vec<_> = [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]
+                .into_iter()
+                .map(|(x, y)| transform.apply(Point::new(x, y)))
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect();
+            // The name sits just above the top-left corner, at the same size however large the frame is
+            let label = Transform {
+                origin: transform.apply(Point::new(-0.5, -0.5)),
+                ..unscaled(transform)
+            };
+            format!(
+                r#"<g><polygon points="{}" fill="none" stroke="{FRAME_COLOR}" stroke-width="{FRAME_WIDTH}"/>{}</g>"#,
+                corners.join(" "),
+                pixel_group(
+                    &label,
+                    &format!(
+                        r#"<text y="-4" font-size="{}" fill="{FRAME_COLOR}">{}</text>"#,
+                        FONT_SIZE * 0.75,
+                        escape(name)
+                    )
+                )
+            )
+        }
+        Item::Table(table) => {
+            let TableItem {
+                transform,
+                column_widths,
+                row_height,
+                cells,
+                stroke,
+            } = table;
+            let (width, height) = (table.width(), table.height());
+            let mut grid = format!(r#"M 0 0 H {width} V {height} H 0 Z"#);
+            let mut x = 0.0;
+            for column_width in column_widths
+                .iter()
+                .take(column_widths.len().saturating_sub(1))
+            {
+                x += column_width;
+                let _ = write!(grid, " M {x} 0 V {height}");
+            }
+            for row in 1..cells.len() {
+                let _ = write!(grid, " M 0 {} H {width}", row as f64 * row_height);
+            }
+            let mut out = format!(
+                r#"<g {}><path d="{grid}" fill="none" {}/>"#,
+                transform_attr(transform),
+                stroke_attrs(stroke)
+            );
+            for (row, row_cells) in cells.iter().enumerate() {
+                let mut x = 0.0;
+                for (text, column_width) in row_cells.iter().zip(column_widths) {
+                    if !text.is_empty() {
+                        let centre = Transform {
+                            origin: Point::new(
+                                x + column_width / 2.0,
+                                (row as f64 + 0.5) * row_height,
+                            ),
+                            ..Transform::default()
+                        };
+                        let lines: Vec<_> = text.split('\n').collect();
+                        out.push_str(&pixel_group(&centre, &text_lines(&lines, FONT_SIZE, "")));
+                    }
+                    x += column_width;
+                }
+            }
+            out.push_str("</g>");
+            out
+        }
+        Item::Connector(ConnectorItem {
+            start,
+            end,
+            stroke,
+            start_marker,
+            end_marker,
+            ..
+        }) => marked_line(*start, *end, stroke, *start_marker, *end_marker),
+    }
+}
+
+/// Build a complete SVG document showing the `view` area of the board
+pub fn render_document<'a>(
+    items: impl IntoIterator<Item = (ItemID, &'a Item)>,
+    view: Rect,
+) -> String {
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        view.min.x,
+        view.min.y,
+        view.width(),
+        view.height(),
+        view.width() * PX_PER_UNIT,
+        view.height() * PX_PER_UNIT,
+    );
+    for (id, item) in items {
+        let _ = write!(out, r#"<g id="item-{}">{}</g>"#, *id, item_to_svg(id, item));
+    }
+    out.push_str("</svg>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn web_and_relative_links_are_kept() {
+        assert!(is_safe_href("https://example.com/a"));
+        assert!(is_safe_href("HTTP://example.com"));
+        assert!(is_safe_href("/board/other"));
+        assert!(is_safe_href("other?x=a:b"));
+        assert!(is_safe_href("#top"));
+    }
+
+    #[test]
+    fn other_schemes_are_dropped() {
+        assert!(!is_safe_href("javascript:alert(1)"));
+        assert!(!is_safe_href(" JavaScript:alert(1)"));
+        assert!(!is_safe_href("java\tscript:alert(1)"));
+        assert!(!is_safe_href("data:text/html,<script>"));
+    }
+}
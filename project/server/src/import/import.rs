@@ -0,0 +1,3 @@
+//! Converting other formats into board items
+
+pub mod svg;
@@ -0,0 +1,922 @@
+//! Converting SVG documents into native items
+//!
+//! Basic shapes, paths and text become the equivalent [`Item`]s. Anything that can't be represented
+//! (images, `<use>`, gradients, clipping, filters, ...) is cut out into its own SVG document and
+//! placed as an [`ImageItem`] so that the imported drawing still looks the same.
+
+use std::{collections::BTreeMap, fmt, io, path::Path, str::FromStr};
+
+use log::warn;
+use roxmltree::{Document, Node, ParsingOptions};
+use svgtypes::{Length, LengthUnit, Paint, PointsParser, SimplePathSegment, ViewBox};
+
+use crate::{
+    canvas::{
+        item::{EllipseItem, ImageItem, LineItem, PathItem, PolygonItem, RectangleItem, TextItem},
+        Color, Fill, Item, LineCap, LineJoin, Marker, Point, Spline, SplineNode, Stroke, TextAlign,
+        TextAnchor, TextStyle, Transform, FONT_SIZE, MAX_DASH_LENGTHS, PX_PER_UNIT,
+    },
+    export::svg::escape,
+    message::{self as m, ErrorCode},
+    upload::{media_info, resolve_media, store_file},
+};
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+
+/// The most parts of a document which can be stored as separate images in one import
+const MAX_FALLBACKS: usize = 32;
+
+/// The most items, including fallback images, which can be created by one import
+const MAX_IMPORTED: usize = 2048;
+
+/// The size of a document with no dimensions, as defined by the SVG specification
+const DEFAULT_VIEWPORT: (f64, f64) = (300.0, 150.0);
+
+/// Properties that are passed from an element to its children
+const INHERITED: &[&str] = &[
+    "color",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "marker-end",
+    "marker-mid",
+    "marker-start",
+    "stroke",
+    "stroke-dasharray",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-opacity",
+    "stroke-width",
+    "text-anchor",
+    "visibility",
+];
+
+/// Elements which define resources instead of drawing anything
+const DEFINITIONS: &[&str] = &[
+    "clipPath",
+    "defs",
+    "filter",
+    "linearGradient",
+    "marker",
+    "mask",
+    "pattern",
+    "radialGradient",
+    "style",
+    "symbol",
+];
+
+/// Elements which never draw anything
+const IGNORED: &[&str] = &["desc", "metadata", "script", "title"];
+
+/// Properties which change how an element is drawn in ways items can't represent
+const EFFECTS: &[&str] = &["clip-path", "filter", "mask"];
+
+/// Reasons an SVG document could not be imported
+#[derive(Debug)]
+pub enum ImportError {
+    /// The URL does not refer to an uploaded file
+    NotFound,
+    /// The file could not be read or a replacement image could not be stored
+    Io(io::Error),
+    /// The file is not valid XML
+    Parse(roxmltree::Error),
+    /// The document is not an SVG
+    NotSvg,
+    /// The document would become more than [`MAX_IMPORTED`] items, or more than [`MAX_FALLBACKS`]
+    /// parts of it would have to be stored as images
+    TooComplex,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "The file does not exist"),
+            Self::Io(e) => write!(f, "Failed to access media: {e}"),
+            Self::Parse(e) => write!(f, "Failed to parse SVG: {e}"),
+            Self::NotSvg => write!(f, "The document is not an SVG"),
+            Self::TooComplex => write!(
+                f,
+                "At most {MAX_IMPORTED} items can be imported from a document, \
+                of which at most {MAX_FALLBACKS} can be images"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<ImportError> for m::Error {
+    fn from(value: ImportError) -> Self {
+        let code = match &value {
+            ImportError::NotFound => ErrorCode::NotFound,
+            ImportError::Io(e) if e.kind() == io::ErrorKind::NotFound => ErrorCode::NotFound,
+            ImportError::Io(_) => ErrorCode::Internal,
+            ImportError::Parse(_) | ImportError::NotSvg | ImportError::TooComplex => {
+                ErrorCode::BadData
+            }
+        };
+        Self {
+            code,
+            msg: Some(value.to_string()),
+        }
+    }
+}
+
+/// The result of converting one element
+#[derive(Debug)]
+pub enum Imported {
+    /// The element was converted directly
+    Item(Item),
+    /// A standalone SVG document containing an element that could not be converted,
+    /// sized so that it lines up with the rest of the import when placed at the same position
+    Fallback(String),
+}
+
+/// Properties inherited from ancestors
+#[derive(Debug, Clone)]
+struct Style<'a> {
+    properties: BTreeMap<&'a str, &'a str>,
+    /// The combined opacity of the element's ancestors, which is applied to its colours
+    opacity: f64,
+}
+
+/// Which dimension a percentage length is relative to
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+    Diagonal,
+}
+
+/// Iterate through the declarations of an element's `style` attribute
+fn declarations<'a>(node: Node<'a, '_>) -> impl Iterator<Item = (&'a str, &'a str)> {
+    node.attribute("style")
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|decl| {
+            let (name, value) = decl.split_once(':')?;
+            let value = value.trim();
+            Some((
+                name.trim(),
+                value.strip_suffix("!important").unwrap_or(value).trim(),
+            ))
+        })
+}
+
+/// Get a property of an element, from its `style` attribute or a presentation attribute
+fn property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    declarations(node)
+        .filter(|(n, _)| *n == name)
+        .map(|(_, v)| v)
+        .last()
+        .or_else(|| node.attribute(name))
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value.trim().parse().ok()
+}
+
+impl<'a> Style<'a> {
+    fn root() -> Self {
+        Self {
+            properties: BTreeMap::new(),
+            opacity: 1.0,
+        }
+    }
+
+    /// Apply the properties set by an element
+    fn child(&self, node: Node<'a, '_>) -> Self {
+        let mut style = self.clone();
+        for name in INHERITED {
+            if let Some(value) = node.attribute(*name) {
+                style.properties.insert(name, value);
+            }
+        }
+        for (name, value) in declarations(node) {
+            if let Some(name) = INHERITED.iter().find(|n| **n == name) {
+                style.properties.insert(name, value);
+            }
+        }
+        if let Some(opacity) = property(node, "opacity").and_then(parse_number) {
+            style.opacity *= opacity.clamp(0.0, 1.0);
+        }
+        style
+    }
+
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.properties.get(name).copied()
+    }
+
+    fn font_size(&self) -> f64 {
+        self.get("font-size")
+            .and_then(|v| Length::from_str(v).ok())
+            .and_then(|l| match l.unit {
+                LengthUnit::None | LengthUnit::Px => Some(l.number),
+                LengthUnit::Pt => Some(l.number * 4.0 / 3.0),
+                LengthUnit::Em => Some(l.number * FONT_SIZE),
+                LengthUnit::Percent => Some(l.number / 100.0 * FONT_SIZE),
+                _ => None,
+            })
+            .unwrap_or(FONT_SIZE)
+    }
+
+    /// Resolve a `fill` or `stroke` paint
+    ///
+    /// Returns `None` for paints that aren't plain colours, such as gradients
+    fn paint(&self, name: &str, default: &str) -> Option<Option<Color>> {
+        let value = self.get(name).unwrap_or(default);
+        let color = match Paint::from_str(value) {
+            Ok(Paint::None) | Err(_) => return Some(None),
+            Ok(Paint::Color(color)) => color,
+            Ok(Paint::CurrentColor) => self
+                .get("color")
+                .and_then(|c| svgtypes::Color::from_str(c).ok())
+                .unwrap_or_else(svgtypes::Color::black),
+            Ok(_) => return None,
+        };
+        let opacity = self
+            .get(&format!("{name}-opacity"))
+            .and_then(parse_number)
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+        let alpha = f64::from(color.alpha) * opacity * self.opacity;
+        Some(Some(Color::from_rgba(
+            color.red,
+            color.green,
+            color.blue,
+            alpha.round() as u8,
+        )))
+    }
+
+    fn fill(&self) -> Option<Option<Color>> {
+        self.paint("fill", "black")
+    }
+
+    fn stroke_color(&self) -> Option<Option<Color>> {
+        self.paint("stroke", "none")
+    }
+
+    fn has_markers(&self) -> bool {
+        ["marker-start", "marker-mid", "marker-end"]
+            .iter()
+            .any(|m| self.get(m).is_some_and(|v| v != "none"))
+    }
+}
+
+fn transparent() -> Color {
+    Color::from_rgba(0, 0, 0, 0)
+}
+
+/// Combine the tangents of the segments either side of a node into a single velocity
+///
+/// Splines can't represent corners with curved sides, so those become sharp corners with straight tangents
+fn node_velocity(incoming: Point, outgoing: Point) -> Point {
+    let length = |p: Point| p.x.hypot(p.y);
+    let (a, b) = (length(incoming), length(outgoing));
+    if a < f64::EPSILON || b < f64::EPSILON {
+        return Point::default();
+    }
+    let cos = (incoming.x * outgoing.x + incoming.y * outgoing.y) / (a * b);
+    if cos > 0.99 {
+        Point::new(
+            (incoming.x + outgoing.x) / 2.0,
+            (incoming.y + outgoing.y) / 2.0,
+        )
+    } else {
+        Point::default()
+    }
+}
+
+/// One continuous part of a `<path>`
+#[derive(Debug, Default)]
+struct Subpath {
+    points: Vec<Point>,
+    incoming: Vec<Point>,
+    outgoing: Vec<Point>,
+    curved: bool,
+    closed: bool,
+}
+
+impl Subpath {
+    fn start(p: Point) -> Self {
+        Self {
+            points: vec![p],
+            incoming: vec![Point::default()],
+            outgoing: vec![Point::default()],
+            ..Default::default()
+        }
+    }
+
+    fn last(&self) -> Point {
+        *self.points.last().expect("Subpaths always have a start")
+    }
+
+    fn line_to(&mut self, p: Point) {
+        self.points.push(p);
+        self.incoming.push(Point::default());
+        self.outgoing.push(Point::default());
+    }
+
+    fn curve_to(&mut self, c1: Point, c2: Point, p: Point) {
+        let from = self.last();
+        *self.outgoing.last_mut().unwrap() = Point::new(c1.x - from.x, c1.y - from.y);
+        self.points.push(p);
+        self.incoming.push(Point::new(p.x - c2.x, p.y - c2.y));
+        self.outgoing.push(Point::default());
+        self.curved = true;
+    }
+
+    fn close(&mut self) {
+        let (first, last) = (self.points[0], self.last());
+        if first.x != last.x || first.y != last.y {
+            self.line_to(first);
+        }
+        self.closed = true;
+    }
+
+    fn to_spline(&self) -> Spline {
+        let last = self.points.len() - 1;
+        let points = (0..=last)
+            .map(|i| SplineNode {
+                position: self.points[i],
+                velocity: match i {
+                    0 => self.outgoing[0],
+                    i if i == last => self.incoming[i],
+                    i => node_velocity(self.incoming[i], self.outgoing[i]),
+                },
+            })
+            .collect();
+        Spline { points }
+    }
+}
+
+fn parse_subpaths(data: &str) -> Vec<Subpath> {
+    let mut subpaths: Vec<Subpath> = Vec::new();
+    // Path data is rendered up to the first error
+    for segment in svgtypes::SimplifyingPathParser::from(data).map_while(Result::ok) {
+        if let SimplePathSegment::MoveTo { x, y } = segment {
+            subpaths.push(Subpath::start(Point::new(x, y)));
+            continue;
+        }
+        let Some(current) = subpaths.last_mut() else {
+            break;
+        };
+        match segment {
+            SimplePathSegment::MoveTo { .. } => unreachable!(),
+            SimplePathSegment::LineTo { x, y } => current.line_to(Point::new(x, y)),
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => current.curve_to(Point::new(x1, y1), Point::new(x2, y2), Point::new(x, y)),
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                let from = current.last();
+                let lerp = |a: f64, b: f64| a + (b - a) * 2.0 / 3.0;
+                current.curve_to(
+                    Point::new(lerp(from.x, x1), lerp(from.y, y1)),
+                    Point::new(lerp(x, x1), lerp(y, y1)),
+                    Point::new(x, y),
+                )
+            }
+            SimplePathSegment::ClosePath => current.close(),
+        }
+    }
+    subpaths.retain(|s| s.points.len() > 1);
+    subpaths
+}
+
+struct Importer<'a, 'input> {
+    source: &'a str,
+    root: Node<'a, 'input>,
+    /// The size of the document in pixels
+    size: (f64, f64),
+    /// The size percentages are relative to
+    viewport: (f64, f64),
+    /// Maps the document's pixels onto the board
+    placement: Transform,
+    out: Vec<Imported>,
+}
+
+impl<'a, 'input> Importer<'a, 'input> {
+    fn length_value(&self, value: &str, axis: Axis, font_size: f64) -> Option<f64> {
+        let length = Length::from_str(value).ok()?;
+        let n = length.number;
+        Some(match length.unit {
+            LengthUnit::None | LengthUnit::Px => n,
+            LengthUnit::Em => n * font_size,
+            LengthUnit::Ex => n * font_size / 2.0,
+            LengthUnit::In => n * 96.0,
+            LengthUnit::Cm => n * 96.0 / 2.54,
+            LengthUnit::Mm => n * 96.0 / 25.4,
+            LengthUnit::Pt => n * 4.0 / 3.0,
+            LengthUnit::Pc => n * 16.0,
+            LengthUnit::Percent => {
+                let (w, h) = self.viewport;
+                let reference = match axis {
+                    Axis::X => w,
+                    Axis::Y => h,
+                    Axis::Diagonal => ((w * w + h * h) / 2.0).sqrt(),
+                };
+                n / 100.0 * reference
+            }
+        })
+    }
+
+    fn length(&self, node: Node, name: &str, axis: Axis, style: &Style) -> f64 {
+        node.attribute(name)
+            // Lists of coordinates (as used by text) only position the first character
+            .and_then(|v| v.split([' ', ',']).find(|s| !s.is_empty()))
+            .and_then(|v| self.length_value(v, axis, style.font_size()))
+            .unwrap_or(0.0)
+    }
+
+    fn stroke_width(&self, style: &Style) -> f64 {
+        style
+            .get("stroke-width")
+            .and_then(|v| self.length_value(v, Axis::Diagonal, style.font_size()))
+            .filter(|&width| width >= 0.0)
+            .unwrap_or(1.0)
+    }
+
+    /// The dash pattern of an element, empty if the pattern is missing, invalid or too long
+    fn dash(&self, style: &Style, scale: f64) -> Vec<f64> {
+        let Some(value) = style.get("stroke-dasharray").filter(|v| *v != "none") else {
+            return Vec::new();
+        };
+        let lengths: Option<Vec<_>> = value
+            .split([' ', ','])
+            .filter(|s| !s.is_empty())
+            .map(|v| self.length_value(v, Axis::Diagonal, style.font_size()))
+            .map(|l| l.filter(|&l| l >= 0.0).map(|l| l * scale))
+            .collect();
+        lengths
+            .filter(|lengths| lengths.len() <= MAX_DASH_LENGTHS)
+            .filter(|lengths| lengths.iter().any(|&l| l > 0.0))
+            .unwrap_or_default()
+    }
+
+    /// The stroke of an element, with its width multiplied by `scale`
+    fn stroke(&self, style: &Style, scale: f64) -> Option<Stroke> {
+        Some(match style.stroke_color()? {
+            Some(color) => Stroke {
+                width: self.stroke_width(style) * scale,
+                color,
+                dash: self.dash(style, scale),
+                cap: match style.get("stroke-linecap") {
+                    Some("round") => LineCap::Round,
+                    Some("square") => LineCap::Square,
+                    _ => LineCap::Butt,
+                },
+                join: match style.get("stroke-linejoin") {
+                    Some("round") => LineJoin::Round,
+                    Some("bevel") => LineJoin::Bevel,
+                    _ => LineJoin::Miter,
+                },
+                // The stroke opacity is already part of the color
+                opacity: 1.0,
+            },
+            None => Stroke {
+                width: 0.0,
+                color: transparent(),
+                dash: Vec::new(),
+                cap: LineCap::Butt,
+                join: LineJoin::Miter,
+                opacity: 1.0,
+            },
+        })
+    }
+
+    fn fill(&self, style: &Style) -> Option<Fill> {
+        Some(Fill::Solid(style.fill()?.unwrap_or_else(transparent)))
+    }
+
+    /// Convert the children of an element
+    fn walk(&mut self, parent: Node<'a, 'input>, transform: &Transform, style: &Style<'a>) {
+        for node in parent.children().filter(Node::is_element) {
+            let tag = node.tag_name();
+            if tag.namespace() != Some(SVG_NS)
+                || DEFINITIONS.contains(&tag.name())
+                || IGNORED.contains(&tag.name())
+                || property(node, "display") == Some("none")
+            {
+                continue;
+            }
+
+            let own_transform = node
+                .attribute("transform")
+                .and_then(|t| svgtypes::Transform::from_str(t).ok())
+                .map(|t| Transform {
+                    origin: Point::new(t.e, t.f),
+                    basis_x: Point::new(t.a, t.b),
+                    basis_y: Point::new(t.c, t.d),
+                })
+                .unwrap_or_default();
+            let node_transform = transform.compose(&own_transform);
+            let node_style = style.child(node);
+
+            let has_effects = EFFECTS
+                .iter()
+                .any(|e| property(node, e).is_some_and(|v| v != "none"));
+            if !has_effects && matches!(tag.name(), "g" | "a" | "switch") {
+                self.walk(node, &node_transform, &node_style);
+                continue;
+            }
+            if node_style.get("visibility").is_some_and(|v| v != "visible") {
+                continue;
+            }
+
+            let items = if has_effects {
+                None
+            } else {
+                self.convert(node, &node_transform, &node_style)
+            };
+            match items {
+                Some(items) => self.out.extend(items.into_iter().map(Imported::Item)),
+                None => {
+                    let fallback = self.fallback(node, transform, style);
+                    self.out.push(Imported::Fallback(fallback));
+                }
+            }
+        }
+    }
+
+    /// Convert a single drawing element, returning `None` if it isn't supported
+    fn convert(&self, node: Node, transform: &Transform, style: &Style) -> Option<Vec<Item>> {
+        let board = self.placement.compose(transform);
+        let x = |name| self.length(node, name, Axis::X, style);
+        let y = |name| self.length(node, name, Axis::Y, style);
+        let d = |name| self.length(node, name, Axis::Diagonal, style);
+
+        let item = match node.tag_name().name() {
+            "rect" => {
+                let (width, height) = (x("width"), y("height"));
+                if width <= 0.0 || height <= 0.0 {
+                    return Some(vec![]);
+                }
+                let shape = Transform {
+                    origin: Point::new(x("x") + width / 2.0, y("y") + height / 2.0),
+                    basis_x: Point::new(width, 0.0),
+                    basis_y: Point::new(0.0, height),
+                };
+                RectangleItem {
+                    transform: board.compose(&shape),
+                    stroke: self.stroke(style, 1.0 / (width * height).sqrt())?,
+                    fill: self.fill(style)?,
+                }
+                .to_item()
+            }
+            name @ ("circle" | "ellipse") => {
+                let (rx, ry) = if name == "circle" {
+                    (d("r"), d("r"))
+                } else {
+                    (x("rx"), y("ry"))
+                };
+                if rx <= 0.0 || ry <= 0.0 {
+                    return Some(vec![]);
+                }
+                let shape = Transform {
+                    origin: Point::new(x("cx"), y("cy")),
+                    basis_x: Point::new(rx * 2.0, 0.0),
+                    basis_y: Point::new(0.0, ry * 2.0),
+                };
+                EllipseItem {
+                    transform: board.compose(&shape),
+                    stroke: self.stroke(style, 1.0 / (2.0 * (rx * ry).sqrt()))?,
+                    fill: self.fill(style)?,
+                }
+                .to_item()
+            }
+            "line" => {
+                if style.has_markers() {
+                    return None;
+                }
+                LineItem {
+                    start: board.apply(Point::new(x("x1"), y("y1"))),
+                    end: board.apply(Point::new(x("x2"), y("y2"))),
+                    stroke: self.stroke(style, board.scale_factor())?,
+                    start_marker: Marker::None,
+                    end_marker: Marker::None,
+                }
+                .to_item()
+            }
+            name @ ("polygon" | "polyline") => {
+                if style.has_markers() {
+                    return None;
+                }
+                let points: Vec<_> = PointsParser::from(node.attribute("points").unwrap_or(""))
+                    .map(|(x, y)| Point::new(x, y))
+                    .collect();
+                if points.len() < 2 {
+                    return Some(vec![]);
+                }
+                if name == "polygon" {
+                    PolygonItem {
+                        points: points.into_iter().map(|p| board.apply(p)).collect(),
+                        stroke: self.stroke(style, board.scale_factor())?,
+                        fill: self.fill(style)?,
+                    }
+                    .to_item()
+                } else if style.fill()?.is_some() {
+                    // Filled polylines are implicitly closed, but their outline isn't
+                    return None;
+                } else {
+                    PathItem {
+                        transform: board,
+                        path: Spline {
+                            points: points
+                                .into_iter()
+                                .map(|position| SplineNode {
+                                    position,
+                                    velocity: Point::default(),
+                                })
+                                .collect(),
+                        },
+                        stroke: self.stroke(style, 1.0)?,
+                        start_marker: Marker::None,
+                        end_marker: Marker::None,
+                    }
+                    .to_item()
+                }
+            }
+            "path" => return self.convert_path(node, board, style),
+            "text" => {
+                let mut text = String::new();
+                for part in node
+                    .descendants()
+                    .filter(Node::is_text)
+                    .filter_map(|n| n.text())
+                {
+                    text.push_str(part);
+                }
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if text.is_empty() {
+                    return Some(vec![]);
+                }
+                let font_size = style.font_size();
+                let align = match style.get("text-anchor") {
+                    Some("middle") => TextAlign::Center,
+                    Some("end") => TextAlign::Right,
+                    _ => TextAlign::Left,
+                };
+                // Text is placed by the middle of its line rather than the baseline
+                let origin = Point::new(x("x"), y("y") - font_size * 0.35);
+                // Text is drawn in pixels, which are the same size as the document's units here
+                TextItem {
+                    transform: Transform {
+                        origin: board.apply(origin),
+                        basis_x: board.apply_vector(Point::new(PX_PER_UNIT, 0.0)),
+                        basis_y: board.apply_vector(Point::new(0.0, PX_PER_UNIT)),
+                    },
+                    text,
+                    style: TextStyle {
+                        font_family: style.get("font-family").unwrap_or("sans-serif").to_string(),
+                        font_size,
+                        color: style.fill()?.unwrap_or_else(transparent),
+                        align,
+                        anchor: TextAnchor::Middle,
+                    },
+                }
+                .to_item()
+            }
+            _ => return None,
+        };
+        Some(vec![item])
+    }
+
+    fn convert_path(&self, node: Node, board: Transform, style: &Style) -> Option<Vec<Item>> {
+        if style.has_markers() {
+            return None;
+        }
+        let subpaths = parse_subpaths(node.attribute("d").unwrap_or(""));
+        let filled = style.fill()?.is_some();
+        // Only straight-sided shapes can be filled
+        if filled && subpaths.iter().any(|s| s.curved) {
+            return None;
+        }
+
+        subpaths
+            .into_iter()
+            .map(|subpath| {
+                Some(if subpath.closed || filled {
+                    // Polygons are already closed, so the closing point isn't needed
+                    let points = match subpath.closed {
+                        true => &subpath.points[..subpath.points.len() - 1],
+                        false => &subpath.points[..],
+                    };
+                    PolygonItem {
+                        points: points.iter().map(|p| board.apply(*p)).collect(),
+                        stroke: self.stroke(style, board.scale_factor())?,
+                        fill: self.fill(style)?,
+                    }
+                    .to_item()
+                } else {
+                    PathItem {
+                        transform: board.clone(),
+                        path: subpath.to_spline(),
+                        stroke: self.stroke(style, 1.0)?,
+                        start_marker: Marker::None,
+                        end_marker: Marker::None,
+                    }
+                    .to_item()
+                })
+            })
+            .collect()
+    }
+
+    /// Create a document containing only `node` and the resources it might use
+    fn fallback(&self, node: Node, transform: &Transform, style: &Style) -> String {
+        use std::fmt::Write;
+
+        let (width, height) = self.size;
+        let mut out = format!(r#"<svg xmlns="{SVG_NS}" width="{width}" height="{height}""#);
+        for namespace in self.root.namespaces() {
+            if let Some(name) = namespace.name() {
+                let _ = write!(out, r#" xmlns:{name}="{}""#, escape(namespace.uri()));
+            }
+        }
+        out.push('>');
+
+        for definition in self.root.descendants().filter(|n| {
+            DEFINITIONS.contains(&n.tag_name().name())
+                && !n
+                    .ancestors()
+                    .skip(1)
+                    .any(|a| DEFINITIONS.contains(&a.tag_name().name()))
+        }) {
+            out.push_str(&self.source[definition.range()]);
+        }
+
+        let _ = write!(
+            out,
+            r#"<g transform="matrix({} {} {} {} {} {})" opacity="{}""#,
+            transform.basis_x.x,
+            transform.basis_x.y,
+            transform.basis_y.x,
+            transform.basis_y.y,
+            transform.origin.x,
+            transform.origin.y,
+            style.opacity
+        );
+        for (name, value) in &style.properties {
+            let _ = write!(out, r#" {name}="{}""#, escape(value));
+        }
+        out.push('>');
+        out.push_str(&self.source[node.range()]);
+        out.push_str("</g></svg>");
+        out
+    }
+}
+
+/// Convert an SVG document into items, placing its top-left corner at `position`
+pub fn convert(source: &str, position: Point) -> Result<Vec<Imported>, ImportError> {
+    let document = Document::parse_with_options(
+        source,
+        ParsingOptions {
+            allow_dtd: false,
+            ..Default::default()
+        },
+    )
+    .map_err(ImportError::Parse)?;
+    let root = document.root_element();
+    if !root.has_tag_name((SVG_NS, "svg")) {
+        return Err(ImportError::NotSvg);
+    }
+
+    let view_box = root
+        .attribute("viewBox")
+        .and_then(|v| ViewBox::from_str(v).ok())
+        .filter(|v| v.w > 0.0 && v.h > 0.0);
+
+    let mut importer = Importer {
+        source,
+        root,
+        size: DEFAULT_VIEWPORT,
+        viewport: DEFAULT_VIEWPORT,
+        placement: Transform {
+            origin: position,
+            basis_x: Point::new(1.0 / PX_PER_UNIT, 0.0),
+            basis_y: Point::new(0.0, 1.0 / PX_PER_UNIT),
+        },
+        out: Vec::new(),
+    };
+
+    // Percentages in the root's own size can't be resolved, so use the view box instead
+    let dimension = |name| {
+        root.attribute(name)
+            .filter(|v| !v.trim_end().ends_with('%'))
+            .and_then(|v| importer.length_value(v, Axis::Diagonal, FONT_SIZE))
+    };
+    let (width, height) = (dimension("width"), dimension("height"));
+    importer.size = match (width, height, view_box) {
+        (Some(w), Some(h), _) => (w, h),
+        (w, h, Some(v)) => (w.unwrap_or(v.w), h.unwrap_or(v.h)),
+        (w, h, None) => (
+            w.unwrap_or(DEFAULT_VIEWPORT.0),
+            h.unwrap_or(DEFAULT_VIEWPORT.1),
+        ),
+    };
+
+    // Fit the view box into the document, centred as with the default `preserveAspectRatio`
+    let transform = match view_box {
+        Some(v) => {
+            let (w, h) = importer.size;
+            let scale = (w / v.w).min(h / v.h);
+            importer.viewport = (v.w, v.h);
+            Transform {
+                origin: Point::new(
+                    (w - v.w * scale) / 2.0 - v.x * scale,
+                    (h - v.h * scale) / 2.0 - v.y * scale,
+                ),
+                basis_x: Point::new(scale, 0.0),
+                basis_y: Point::new(0.0, scale),
+            }
+        }
+        None => {
+            importer.viewport = importer.size;
+            Transform::default()
+        }
+    };
+
+    importer.walk(root, &transform, &Style::root().child(root));
+
+    let fallbacks = importer
+        .out
+        .iter()
+        .filter(|imported| matches!(imported, Imported::Fallback(_)))
+        .count();
+    if importer.out.len() > MAX_IMPORTED || fallbacks > MAX_FALLBACKS {
+        return Err(ImportError::TooComplex);
+    }
+    Ok(importer.out)
+}
+
+/// Import an uploaded SVG file, storing any parts that can't be converted as separate images
+pub async fn import_svg(
+    url: &str,
+    position: Point,
+    media_root: &Path,
+) -> Result<Vec<Item>, ImportError> {
+    let path = resolve_media(media_root, url).ok_or(ImportError::NotFound)?;
+    let source = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(ImportError::Io)?;
+    let name = path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("import");
+
+    let mut items = Vec::new();
+    for (index, imported) in convert(&source, position)?.into_iter().enumerate() {
+        items.push(match imported {
+            Imported::Item(item) => item,
+            Imported::Fallback(document) => {
+                let file_name = format!("{name}-{index}.svg");
+                let stored = store_file(media_root, &file_name, document.as_bytes()).await;
+                let resource = match stored {
+                    Ok(resource) => resource,
+                    Err(e) => {
+                        discard_import(&items, media_root).await;
+                        return Err(ImportError::Io(e));
+                    }
+                };
+                let url = format!("/media/{resource}");
+                ImageItem {
+                    transform: Transform {
+                        origin: position,
+                        ..Default::default()
+                    },
+                    info: media_info(media_root, &url).await,
+                    url,
+                    description: format!("Part of {name}"),
+                    crop: None,
+                }
+                .to_item()
+            }
+        });
+    }
+    Ok(items)
+}
+
+/// Delete the images stored by [`import_svg`] for items which won't be added to the board
+///
+/// Every image in an import is a fallback stored for it, so all of them are removed
+pub async fn discard_import(items: &[Item], media_root: &Path) {
+    for item in items {
+        let Item::Image(image) = item else {
+            continue;
+        };
+        let Some(path) = resolve_media(media_root, &image.url) else {
+            continue;
+        };
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            warn!("Failed to remove {}: {e}", path.display());
+        }
+    }
+}
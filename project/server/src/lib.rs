@@ -8,6 +8,10 @@ pub mod board;
 #[path = "canvas/canvas.rs"]
 pub mod canvas;
 pub mod client;
+#[path = "export/export.rs"]
+pub mod export;
+#[path = "import/import.rs"]
+pub mod import;
 #[path = "message/message.rs"]
 pub mod message;
 #[path = "tags/tags.rs"]
@@ -15,10 +19,14 @@ pub mod tags;
 pub mod upload;
 mod utils;
 
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use board::BoardManager;
 use client::{create_client_filter, SessionRegistry};
+use export::create_export_filter;
 use upload::create_upload_filter;
 use warp::{filters::BoxedFilter, reply::Reply, Filter};
 
@@ -26,6 +34,7 @@ pub use upload::create_media_filter;
 
 /// Global options for the application
 #[derive(derive_builder::Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Configuration {
     /// Path to static files
     pub static_root: PathBuf,
@@ -35,6 +44,23 @@ pub struct Configuration {
     pub media_root: PathBuf,
     /// Whether or not to serve TypeScript files as well as generated JS
     pub serve_ts: bool,
+    /// How often to ping connected clients
+    pub heartbeat_interval: Duration,
+    /// How long a client can go without sending anything (including pongs) before it is disconnected
+    pub heartbeat_timeout: Duration,
+}
+
+impl ConfigurationBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let (Some(interval), Some(timeout)) = (self.heartbeat_interval, self.heartbeat_timeout) {
+            if timeout <= interval {
+                return Err(format!(
+                    "heartbeat_timeout ({timeout:?}) must be longer than heartbeat_interval ({interval:?})"
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A container of all resources shared across parts of the application
@@ -84,6 +110,7 @@ pub fn create_api_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
     create_start_time_filter()
         .or(create_client_filter(res))
         .or(create_upload_filter(res))
+        .or(create_export_filter(res))
         .boxed()
 }
 
@@ -1,13 +1,19 @@
 use std::time::Duration;
 
 use camino::Utf8PathBuf;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use flexi_logger::Logger;
 use log::{error, info};
 use tokio::runtime;
 use virtual_whiteboard::{
-    board::BoardManager, create_api_filter, create_media_filter, create_script_filter,
-    create_static_filter, ConfigurationBuilder, GlobalRes, GlobalResources,
+    board::BoardManager,
+    create_api_filter, create_media_filter, create_script_filter, create_static_filter,
+    export::{
+        pdf::{render_pdf, PdfOptions},
+        png::{render_png, PngOptions},
+        ExportOptions,
+    },
+    ConfigurationBuilder, GlobalRes, GlobalResources,
 };
 use warp::{filters::BoxedFilter, reply::Reply, Filter};
 
@@ -30,6 +36,62 @@ struct Args {
 
     #[arg(long, default_value_t = true)]
     serve_ts: bool,
+
+    /// Seconds between heartbeat pings
+    #[arg(long, default_value_t = 10)]
+    heartbeat_interval: u64,
+
+    /// Seconds of silence before a client is considered disconnected
+    #[arg(long, default_value_t = 30)]
+    heartbeat_timeout: u64,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Alternatives to running the server
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render a board to a PNG file
+    ExportPng {
+        /// The name of the board to render
+        board: String,
+
+        /// Where to write the image
+        output: Utf8PathBuf,
+
+        /// Pixels per board unit
+        #[arg(long, default_value_t = PngOptions::default().scale)]
+        scale: f64,
+
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+
+    /// Render a board to a PDF file
+    ExportPdf {
+        /// The name of the board to render
+        board: String,
+
+        /// Where to write the document
+        output: Utf8PathBuf,
+
+        #[command(flatten)]
+        pdf_options: PdfOptions,
+
+        #[command(flatten)]
+        options: ExportOptions,
+    },
+
+    /// Change how far paths drawn on a board may be simplified
+    SetPathTolerance {
+        /// The name of the board to change
+        board: String,
+
+        /// How far (in board units) a simplified path may stray from the points that were drawn,
+        /// zero keeps paths as they were drawn
+        tolerance: f64,
+    },
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -44,8 +106,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .script_root(args.script_root.into())
         .media_root(args.media_root.into())
         .serve_ts(args.serve_ts)
-        .build()
-        .unwrap();
+        .heartbeat_interval(Duration::from_secs(args.heartbeat_interval))
+        .heartbeat_timeout(Duration::from_secs(args.heartbeat_timeout))
+        .build()?;
 
     info!("Program Startup");
 
@@ -56,21 +119,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .enable_time()
         .worker_threads(2)
         .build()
-        .map_err(|e| {
-            error!("Failed to build Tokio runtime: {}", &e);
-            e
-        })?;
+        .inspect_err(|e| error!("Failed to build Tokio runtime: {e}"))?;
 
     info!("Successfully constructed Tokio runtime");
 
     runtime.block_on(async move {
         info!("Loading boards");
-        let boards = BoardManager::new(Box::leak(
-            args.board_root
-                .as_std_path()
-                .to_path_buf()
-                .into_boxed_path(),
-        ));
+        let boards = BoardManager::new(
+            Box::leak(
+                args.board_root
+                    .as_std_path()
+                    .to_path_buf()
+                    .into_boxed_path(),
+            ),
+            config.media_root.clone(),
+        )
+        .await;
+
+        if let Some(command) = args.command {
+            return run_command(command, &boards, config.media_root).await;
+        }
 
         let res = GlobalResources::new(boards, config).as_static();
 
@@ -90,10 +158,55 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let filter = create_filter(res);
 
         info!("Starting server");
-        warp::serve(filter).bind(([0, 0, 0, 0], 8080)).await
-    });
+        warp::serve(filter).bind(([0, 0, 0, 0], 8080)).await;
+        Ok(())
+    })
+}
 
-    Ok(())
+async fn run_command(
+    command: Command,
+    boards: &BoardManager,
+    media_root: std::path::PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::ExportPng {
+            board,
+            output,
+            scale,
+            options,
+        } => {
+            options.validate()?;
+            let canvas = boards
+                .get_canvas(&board)
+                .await
+                .ok_or_else(|| format!("No board named {board}"))?;
+            let png = render_png(&canvas, &options, PngOptions { scale }, media_root).await?;
+            tokio::fs::write(&output, png).await?;
+            info!("Exported {board} to {output}");
+            Ok(())
+        }
+        Command::ExportPdf {
+            board,
+            output,
+            pdf_options,
+            options,
+        } => {
+            options.validate()?;
+            let canvas = boards
+                .get_canvas(&board)
+                .await
+                .ok_or_else(|| format!("No board named {board}"))?;
+            let pdf = render_pdf(&canvas, &options, &pdf_options, media_root).await?;
+            tokio::fs::write(&output, pdf).await?;
+            info!("Exported {board} to {output}");
+            Ok(())
+        }
+        Command::SetPathTolerance { board, tolerance } => {
+            boards.set_path_tolerance(&board, tolerance).await?;
+            info!("Set the path tolerance of {board} to {tolerance}");
+            Ok(())
+        }
+    }
 }
 
 fn create_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
@@ -105,10 +218,10 @@ fn create_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
     let script_filter = warp::path("script").and(create_script_filter(res));
     let media_filter = warp::path("media").and(create_media_filter(res));
 
-    return api_filter
+    api_filter
         .or(static_filter)
         .or(index_filter)
         .or(script_filter)
         .or(media_filter)
-        .boxed();
+        .boxed()
 }
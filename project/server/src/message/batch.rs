@@ -0,0 +1,47 @@
+//! Several method calls sent in a single frame
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+#[cfg(feature = "codegen")]
+use ts_rs::TS;
+
+use super::{method::Methods, notify_c::NotifyC};
+
+/// The largest number of calls accepted in one batch
+pub static MAX_BATCH_LENGTH: usize = 256;
+
+/// A set of method calls to be handled together
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct BatchCall {
+    /// The ID for the client to associate the response with the batch
+    pub id: u32,
+    /// If set, either every call in the batch takes effect or none of them do
+    #[serde(default)]
+    #[cfg_attr(feature = "codegen", ts(optional))]
+    pub atomic: Option<bool>,
+    /// The calls, handled in order
+    pub calls: Vec<Methods>,
+}
+
+/// The combined replies to a [`BatchCall`]
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct BatchResponse {
+    /// See [`BatchCall::id`]
+    pub id: u32,
+    /// Whether the changes made by the batch were kept
+    pub applied: bool,
+    /// The responses and rejections sent by each call, in order
+    #[cfg_attr(feature = "codegen", ts(type = "unknown[]"))]
+    pub messages: Vec<Box<RawValue>>,
+}
+
+/// Notifications produced by a batch, sent as one message
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct NotifyCBatch {
+    /// The notifications, in the order they were produced, each as it would be sent in a `Notify-C` message
+    #[cfg_attr(feature = "codegen", ts(type = "unknown[]"))]
+    pub notifications: Vec<NotifyC>,
+}
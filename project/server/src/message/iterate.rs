@@ -92,7 +92,7 @@ impl<M: IterateType> IterateHandle<M> {
     }
 
     pub fn flush_response(&mut self) {
-        let items = mem::replace(&mut self.current_items, Vec::new());
+        let items = mem::take(&mut self.current_items);
         let response = IterateResponse::<M> {
             id: self.id,
             complete: false,
@@ -217,7 +217,7 @@ iterate_declarations! {
 
     GetFullItems(
         ids: Vec<ItemID>,
-    ) => super::Result<(ItemID, Item)>
+    ) => super::Result<(ItemID, Item, u32)>
 
     GetActivePath(
         path: PathID,
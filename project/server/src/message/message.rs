@@ -1,5 +1,6 @@
 //! Types associated with communication between client and server
 
+pub mod batch;
 pub mod iterate;
 pub mod method;
 pub mod notify_c;
@@ -11,7 +12,7 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "codegen")]
 use ts_rs::TS;
 
-use crate::canvas::{Color, Point, Stroke, Transform};
+use crate::canvas::{Point, RecognizedShape, Transform};
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "protocol")]
@@ -21,6 +22,8 @@ pub enum MsgRecv {
     Method(method::Methods),
     /// A method call expecting a streamed response
     Iterate(iterate::Iterates),
+    /// Several method calls handled together
+    Batch(batch::BatchCall),
 }
 
 /// A message sent to a client
@@ -40,6 +43,14 @@ pub enum MsgSend {
 
     /// A failure message
     Reject(reject::RejectMessage),
+
+    /// The replies to a batch of method calls
+    #[serde(rename = "Batch-Response")]
+    BatchResponse(batch::BatchResponse),
+
+    /// Several notifications sent together
+    #[serde(rename = "Notify-C-Batch")]
+    NotifyCBatch(batch::NotifyCBatch),
 }
 
 #[derive(Serialize, Debug)]
@@ -56,11 +67,13 @@ pub enum ErrorCode {
     EmptyPath,
     /// Data provided is incompatible with the target operation
     BadData,
+    /// The resource has changed since the revision the request expected
+    Conflict,
 }
 
-impl Into<Error> for ErrorCode {
-    fn into(self) -> Error {
-        Error::code(self)
+impl From<ErrorCode> for Error {
+    fn from(code: ErrorCode) -> Self {
+        Error::code(code)
     }
 }
 
@@ -132,6 +145,31 @@ pub struct ClientState {
     pub paths: Vec<PathID>,
     pub selected_items: Vec<(ItemID, Transform)>,
     pub selection_transform: Transform,
+    /// Round-trip time of the client's most recent heartbeat in milliseconds
+    pub latency: Option<f64>,
+}
+
+/// The item created from a finished path
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+#[serde(rename_all = "camelCase")]
+pub struct EndedPath {
+    /// The ID of the new item
+    pub item_id: ItemID,
+    /// The shape the path was drawn as, if shape recognition was requested and successful
+    pub shape: Option<RecognizedShape>,
+}
+
+/// A frame on the board, for navigating between them
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct FrameInfo {
+    #[allow(missing_docs)]
+    pub id: ItemID,
+    #[allow(missing_docs)]
+    pub name: String,
+    /// The area covered by the frame, as in [`crate::canvas::item::FrameItem`]
+    pub transform: Transform,
 }
 
 /// Identification provided to clients
@@ -150,6 +188,8 @@ pub struct ConnectionInfo {
 /// A private ID used to verify reconnects
 pub struct SessionID(u32);
 
+// IDs are created deliberately, so they have no default
+#[allow(clippy::new_without_default)]
 impl SessionID {
     /// Atomically create a new unique [`SessionID`]
     pub fn new() -> Self {
@@ -171,6 +211,8 @@ impl FromStr for SessionID {
 /// A public ID shared with other clients
 pub struct ClientID(u32);
 
+// IDs are created deliberately, so they have no default
+#[allow(clippy::new_without_default)]
 impl ClientID {
     /// Atomically create a new uniquw [`ClientID`]
     pub fn new() -> Self {
@@ -192,6 +234,8 @@ pub struct ItemID(pub u32);
 #[cfg_attr(feature = "codegen", derive(TS))]
 pub struct PathID(pub u32);
 
+// IDs are created deliberately, so they have no default
+#[allow(clippy::new_without_default)]
 impl PathID {
     /// Atomically create a new unique [`PathID`]
     pub fn new() -> Self {
@@ -199,6 +243,27 @@ impl PathID {
     }
 }
 
+/// A board-unique ID for each [`crate::canvas::comment::CommentThread`]
+#[derive(
+    Serialize, Deserialize, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy,
+)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct ThreadID(pub u32);
+
+/// Identifies a board regardless of the name it is stored under
+#[derive(Serialize, Deserialize, Deref, PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct BoardID(pub u32);
+
+// IDs are created deliberately, so they have no default
+#[allow(clippy::new_without_default)]
+impl BoardID {
+    /// Pick a new random [`BoardID`]
+    pub fn new() -> Self {
+        Self(rand::random())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[cfg_attr(feature = "codegen", derive(TS))]
 /// A piece of location data which could describe either a [`Transform`] or [`Point`]-based [`crate::canvas::Item`]
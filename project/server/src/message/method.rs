@@ -20,15 +20,65 @@ pub trait MethodType {
 
     /// The type that should be sent back to the client
     #[cfg(not(feature = "codegen"))]
-    type Response: Serialize + Sized;
+    type Response: Serialize + Sized + Outcome;
 
     #[cfg(feature = "codegen")]
-    type Response: Serialize + Sized + TS;
+    type Response: Serialize + Sized + Outcome + TS;
 
     /// Wrap self in the [`Responses`] enum
     fn wrap_response(data: Response<Self>) -> Responses;
 }
 
+/// Whether a method call was carried out, returned by each method handler so that atomic batches know when to roll back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    /// The call took full effect
+    Completed,
+    /// The call was refused or only partly carried out
+    Failed,
+}
+
+/// Whether a response means the request was not fully carried out
+pub trait Outcome {
+    /// Check if the response reports a failure
+    fn is_failure(&self) -> bool;
+}
+
+impl<T, E> Outcome for super::Result<T, E> {
+    fn is_failure(&self) -> bool {
+        matches!(self, super::Result::Err(_))
+    }
+}
+
+impl<T: Outcome> Outcome for Vec<T> {
+    fn is_failure(&self) -> bool {
+        self.iter().any(Outcome::is_failure)
+    }
+}
+
+macro_rules! infallible_outcomes {
+    ($($t:ty),*) => {
+        $(
+            impl Outcome for $t {
+                fn is_failure(&self) -> bool {
+                    false
+                }
+            }
+        )*
+    };
+}
+
+infallible_outcomes!(
+    (),
+    super::ItemID,
+    super::PathID,
+    super::ClientID,
+    super::ClientState,
+    super::FrameInfo,
+    super::ThreadID,
+    crate::canvas::comment::CommentThread
+);
+
 #[derive(Deserialize, Debug)]
 #[cfg_attr(feature = "codegen", derive(TS))]
 /// An object representing a method call packet
@@ -63,11 +113,17 @@ impl<T: MethodType> Call<T> {
 
 impl<T: MethodType> MethodHandle<T> {
     /// Send a response to the client
-    pub fn respond(self, value: T::Response) {
+    pub fn respond(self, value: T::Response) -> CallOutcome {
+        let outcome = if value.is_failure() {
+            CallOutcome::Failed
+        } else {
+            CallOutcome::Completed
+        };
         if let Some(client) = self.client {
             let response = T::wrap_response(Response { id: self.id, value });
             client.send_message(MsgSend::Response(response));
         }
+        outcome
     }
 
     fn send_reject(&self, reason: RejectReason, level: RejectLevel) {
@@ -83,8 +139,9 @@ impl<T: MethodType> MethodHandle<T> {
     }
 
     /// Reply with an error rejection
-    pub fn error(self, reason: RejectReason) {
-        self.send_reject(reason, RejectLevel::Error)
+    pub fn error(self, reason: RejectReason) -> CallOutcome {
+        self.send_reject(reason, RejectLevel::Error);
+        CallOutcome::Failed
     }
 
     /// Reply with a warning rejection
@@ -95,12 +152,12 @@ impl<T: MethodType> MethodHandle<T> {
 
 impl<T: MethodType<Response = super::Result<TOk, TErr>>, TOk, TErr> MethodHandle<T> {
     /// Construct a return packet from a [`super::Result::Ok`] value
-    pub fn ok(self, value: TOk) {
+    pub fn ok(self, value: TOk) -> CallOutcome {
         self.respond(super::Result::Ok(value))
     }
 
     /// Construct a return packet from a [`super::Result::Err`] value
-    pub fn err(self, value: TErr) {
+    pub fn err(self, value: TErr) -> CallOutcome {
         self.respond(super::Result::Err(value))
     }
 }
@@ -194,8 +251,15 @@ pub use _methods::*;
 mod _methods {
     use super::*;
     use crate::{
-        canvas::{Item, SplineNode, Stroke, Transform},
-        message::{self as m, ClientID, ClientState, ItemID, LocationUpdate, PathID},
+        canvas::{
+            comment::{CommentAnchor, CommentThread},
+            item::BoardLink,
+            Item, Point, SplineNode, Stroke, Transform,
+        },
+        message::{
+            self as m, ClientID, ClientState, EndedPath, FrameInfo, ItemID, LocationUpdate, PathID,
+            ThreadID,
+        },
     };
 
     method_declarations! {
@@ -222,8 +286,26 @@ mod _methods {
         // /// Apply a [`BatchChanges`] to the set of items
         // fn EditBatchItems(ids: Vec<ItemID>, changes: BatchChanges,) => Vec<m::Result>
 
-        /// Replace/Merge \[TODO: Clarify/decide] an item with a new item
-        fn EditSingleItem(item_id: ItemID, item: Item,) => m::Result
+        /// Replace/Merge \[TODO: Clarify/decide] an item with a new item.
+        /// If `expected_revision` is given and the item has changed since, the edit fails with [`m::ErrorCode::Conflict`]
+        fn EditSingleItem(
+            item_id: ItemID,
+            item: Item,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Apply a JSON merge patch (RFC 7396) to an item, leaving any properties not in the patch unchanged.
+        /// See [`EditSingleItem`] for `expected_revision`
+        fn PatchSingleItem(
+            item_id: ItemID,
+            #[cfg_attr(feature = "codegen", ts(type = "unknown"))]
+            patch: serde_json::Value,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
 
         /// Delete multiple items from the board
         fn DeleteItems(ids: Vec<ItemID>,) => ()
@@ -231,18 +313,117 @@ mod _methods {
         /// Create a new item
         fn CreateItem(item: Item,) => ItemID
 
-        /// Start a new path
-        fn BeginPath(stroke: Stroke,) => PathID
+        /// Convert an uploaded SVG file into items, with its top-left corner at `position`
+        fn ImportSvg(url: String, position: Point,) => m::Result<Vec<ItemID>>
+
+        /// Insert a row into a table before `index`, or at the end if `index` is the number of rows.
+        /// Table edits don't need the table to be selected, so several clients can edit it at once,
+        /// but they are refused while another client has it selected.
+        /// See [`EditSingleItem`] for `expected_revision`
+        fn TableInsertRow(
+            item_id: ItemID,
+            index: u32,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            cells: Option<Vec<String>>,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Remove a row from a table, see [`TableInsertRow`]
+        fn TableDeleteRow(
+            item_id: ItemID,
+            index: u32,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Insert an empty column into a table, see [`TableInsertRow`]
+        fn TableInsertColumn(
+            item_id: ItemID,
+            index: u32,
+            width: f64,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Remove a column from a table, see [`TableInsertRow`]
+        fn TableDeleteColumn(
+            item_id: ItemID,
+            index: u32,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Replace the text of one cell of a table, see [`TableInsertRow`]
+        fn TableEditCell(
+            item_id: ItemID,
+            row: u32,
+            column: u32,
+            text: String,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            expected_revision: Option<u32>,
+        ) => m::Result
+
+        /// Start a discussion on an item or a place on the board, with `text` as its first comment
+        fn CreateThread(anchor: CommentAnchor, text: String,) => m::Result<ThreadID>
+
+        /// Add a comment to the end of a thread
+        fn ReplyToThread(thread_id: ThreadID, text: String,) => m::Result
+
+        /// Mark a thread as settled, it still accepts replies
+        fn ResolveThread(thread_id: ThreadID,) => m::Result
+
+        /// Undo [`ResolveThread`]
+        fn ReopenThread(thread_id: ThreadID,) => m::Result
+
+        /// Start a new path.
+        /// If `recognize_shapes` is set, the finished path is replaced by a line or closed shape when it resembles one
+        fn BeginPath(
+            stroke: Stroke,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            recognize_shapes: Option<bool>,
+        ) => PathID
 
         /// Continue the path
         fn ContinuePath(path_id: PathID, points: Vec<SplineNode>,) => ()
 
-        /// Close the path
-        fn EndPath(path_id: PathID,) => m::Result<ItemID>
+        /// Close the path.
+        /// `recognize_shapes` overrides the choice made in [`BeginPath`]
+        fn EndPath(
+            path_id: PathID,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            recognize_shapes: Option<bool>,
+        ) => m::Result<EndedPath>
 
         /// Get a list of every ID on the board
         fn GetAllItemIDs() => Vec<ItemID>
 
+        /// Get the items under a point, topmost first.
+        /// Thin items such as lines are also found within `tolerance` of the point
+        fn GetItemsAtPoint(
+            point: Point,
+            #[serde(default)]
+            #[cfg_attr(feature = "codegen", ts(optional))]
+            tolerance: Option<f64>,
+        ) => Vec<ItemID>
+
+        /// Get every frame on the board, in the order they were created
+        fn GetFrames() => Vec<FrameInfo>
+
+        /// Get every comment thread on the board, in the order they were started
+        fn GetAllThreads() => Vec<CommentThread>
+
+        /// Find where a link item leads, with the board's current name if it has been renamed
+        fn FollowLink(item_id: ItemID,) => m::Result<BoardLink>
+
         /// Get a list of every client ID
         fn GetAllClientIDs() => Vec<ClientID>
 
@@ -1,15 +1,19 @@
 #![allow(missing_docs)] // API is documented in design section
 //! Types associated with server-to-client notification messages
 
-use crate::canvas::{Item, Stroke, Transform};
+use crate::canvas::{
+    comment::{Comment, CommentAnchor, CommentThread},
+    Item, Stroke, Transform,
+};
 
-use super::{ClientID, ClientInfo, ItemID, LocationUpdate, MsgSend, PathID};
+use super::{ClientID, ClientInfo, ItemID, LocationUpdate, MsgSend, PathID, ThreadID};
 use paste::paste;
 use serde::Serialize;
 #[cfg(feature = "codegen")]
 use ts_rs::TS;
 
 /// An individual Notify-C message type that can be converted into a message
+#[allow(clippy::wrong_self_convention)]
 pub trait NotifyCType: Sized {
     /// Wrap self into an instance of the [`NotifyC`] enumeration
     fn as_notify(self) -> NotifyC;
@@ -111,9 +115,11 @@ notify_c_declarations! {
 
     SelectionItemsRemoved (
         id: ClientID,
-        items: Vec<(ItemID, LocationUpdate)>,
+        /// The final location of each item and its revision afterwards
+        items: Vec<(ItemID, LocationUpdate, u32)>,
     )
 
+    /// Items are only changed once they are removed from the selection, so this has no revisions
     SelectionMoved (
         id: ClientID,
         transform: Transform,
@@ -125,6 +131,14 @@ notify_c_declarations! {
     SingleItemEdited (
         id: ItemID,
         item: Item,
+        revision: u32,
+    )
+
+    SingleItemPatched (
+        id: ItemID,
+        #[cfg_attr(feature = "codegen", ts(type = "unknown"))]
+        patch: serde_json::Value,
+        revision: u32,
     )
 
     ItemsDeleted (
@@ -135,6 +149,7 @@ notify_c_declarations! {
         id: ItemID,
         client: ClientID,
         item: Item,
+        revision: u32,
     )
 
     PathStarted (
@@ -142,6 +157,66 @@ notify_c_declarations! {
         stroke: Stroke,
         path: PathID,
     )
+
+    TableRowInserted (
+        id: ItemID,
+        index: u32,
+        cells: Vec<String>,
+        revision: u32,
+    )
+
+    TableRowDeleted (
+        id: ItemID,
+        index: u32,
+        revision: u32,
+    )
+
+    TableColumnInserted (
+        id: ItemID,
+        index: u32,
+        width: f64,
+        revision: u32,
+    )
+
+    TableColumnDeleted (
+        id: ItemID,
+        index: u32,
+        revision: u32,
+    )
+
+    TableCellEdited (
+        id: ItemID,
+        row: u32,
+        column: u32,
+        text: String,
+        revision: u32,
+    )
+
+    ThreadCreated (
+        thread: CommentThread,
+        client: ClientID,
+    )
+
+    CommentAdded (
+        thread: ThreadID,
+        comment: Comment,
+    )
+
+    ThreadResolved (
+        id: ThreadID,
+        client: ClientID,
+    )
+
+    ThreadReopened (
+        id: ThreadID,
+        client: ClientID,
+    )
+
+    /// The item a thread was attached to has been deleted
+    ThreadMoved (
+        id: ThreadID,
+        anchor: CommentAnchor,
+    )
 }
 
 impl NotifyC {
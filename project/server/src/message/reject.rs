@@ -47,6 +47,12 @@ pub enum RejectReason {
         id_type: &'static str,
         value: u32,
     },
+    NonExistentBoard {
+        name: String,
+    },
+    NonExistentMedia {
+        url: String,
+    },
     IncorrectType {
         #[serde(skip_serializing_if = "Option::is_none")]
         key: Option<String>,
@@ -30,6 +30,8 @@ resource_types! {
     crate::message::ItemID: "Item"
     crate::message::PathID: "Path"
     crate::message::ClientID: "Client"
+    crate::message::ThreadID: "Thread"
+    crate::message::BoardID: "Board"
 }
 
 /// Shorthand for creating a [`RejectReason::ResourceNotOwned`]
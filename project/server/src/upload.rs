@@ -1,13 +1,18 @@
 //! API routes for file uploads
 
 use std::{
-    io,
-    path::Path,
+    fs::File,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use futures_util::StreamExt;
+use resvg::usvg::{ImageHrefResolver, Options, Tree, TreeParsing};
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
+#[cfg(feature = "codegen")]
+use ts_rs::TS;
 use warp::{
     filters::{
         multipart::{FormData, Part},
@@ -19,10 +24,31 @@ use warp::{
 
 use crate::{utils::counter, GlobalRes};
 
+/// Added to the name of an upload for the file next to it which records its [`MediaInfo`]
+const INFO_SUFFIX: &str = ".info.json";
+
+/// How much of a file is read to find the size of a raster image, which is recorded in its header
+const HEADER_LENGTH: u64 = 256 * 1024;
+
+/// The largest SVG file that is parsed to find its size
+const MAX_SVG_LENGTH: u64 = 8 * 1024 * 1024;
+
+/// What the server knows about a stored image
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "codegen", derive(TS))]
+pub struct MediaInfo {
+    /// The width in pixels
+    pub width: u32,
+    /// The height in pixels
+    pub height: u32,
+    /// Detected from the contents of the file rather than its name
+    pub mime: String,
+}
+
 /// Get a semi-unique ID for a file by combining the current time with an execution-unique value
 ///
 /// The only way collisions could occur would be if multiple instances were running in parallel, which would already be a bad idea
-fn get_file_id() -> String {
+pub(crate) fn get_file_id() -> String {
     let time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("This code should not be running before the UNIX epoch")
@@ -56,7 +82,123 @@ async fn try_upload_part(target: &Path, mut part: Part) -> io::Result<String> {
 
     file.flush().await?;
     file.sync_all().await?;
-    return Ok(resource_path);
+
+    // Not every upload is an image, so there may be nothing to record
+    media_info(target, &format!("{MEDIA_PREFIX}{resource_path}")).await;
+
+    Ok(resource_path)
+}
+
+/// The start of the URL of every stored file
+const MEDIA_PREFIX: &str = "/media/";
+
+/// Map a media URL as produced by the client (`/media/<id>/<name>`) to a file in `media_root`
+///
+/// Anything else, including paths that would escape `media_root`, is rejected
+pub(crate) fn resolve_media(media_root: &Path, href: &str) -> Option<PathBuf> {
+    let rest = Path::new(href.strip_prefix(MEDIA_PREFIX)?);
+    if rest.as_os_str().is_empty() || !rest.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(media_root.join(rest))
+}
+
+/// The MIME types of the image formats browsers can display
+fn image_mime(kind: imagesize::ImageType) -> Option<&'static str> {
+    use imagesize::ImageType;
+    Some(match kind {
+        ImageType::Avif => "image/avif",
+        ImageType::Bmp => "image/bmp",
+        ImageType::Gif => "image/gif",
+        ImageType::Ico => "image/vnd.microsoft.icon",
+        ImageType::Jpeg => "image/jpeg",
+        ImageType::Png => "image/png",
+        ImageType::Webp => "image/webp",
+        _ => return None,
+    })
+}
+
+/// Options for parsing an SVG only to find its size
+///
+/// Images it refers to are never loaded, so it can't make the server read other files
+fn size_only_options() -> Options {
+    Options {
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: Box::new(|_, _, _| None),
+            resolve_string: Box::new(|_, _| None),
+        },
+        ..Default::default()
+    }
+}
+
+/// Work out the size and type of an image file, or [`None`] if it isn't one
+///
+/// Only the start of the file is read, unless it is an SVG
+fn inspect_image(path: &Path) -> Option<MediaInfo> {
+    let mut file = File::open(path).ok()?;
+    let mut data = Vec::new();
+    file.by_ref()
+        .take(HEADER_LENGTH)
+        .read_to_end(&mut data)
+        .ok()?;
+    let (mime, width, height) = match imagesize::image_type(&data) {
+        Ok(kind) => {
+            let size = imagesize::blob_size(&data).ok()?;
+            (image_mime(kind)?, size.width as u32, size.height as u32)
+        }
+        // SVG is text, so it has no header to recognise
+        Err(_) => {
+            if file.metadata().ok()?.len() > MAX_SVG_LENGTH {
+                return None;
+            }
+            file.read_to_end(&mut data).ok()?;
+            let tree = Tree::from_data(&data, &size_only_options()).ok()?;
+            let size = tree.size;
+            let (width, height) = (size.width().ceil(), size.height().ceil());
+            ("image/svg+xml", width as u32, height as u32)
+        }
+    };
+    Some(MediaInfo {
+        width,
+        height,
+        mime: mime.to_string(),
+    })
+}
+
+/// Get the [`MediaInfo`] of the image at a media URL, or [`None`] if there is no such image
+///
+/// The result of inspecting a file is recorded next to it, so that it only needs to be done once
+pub(crate) async fn media_info(media_root: &Path, href: &str) -> Option<MediaInfo> {
+    let path = resolve_media(media_root, href)?;
+    let name = path.file_name()?.to_str()?;
+    if !path.is_file() || name.ends_with(INFO_SUFFIX) {
+        return None;
+    }
+    let info_path = path.with_file_name(format!("{name}{INFO_SUFFIX}"));
+
+    if let Ok(data) = tokio::fs::read(&info_path).await {
+        if let Ok(info) = serde_json::from_slice(&data) {
+            return Some(info);
+        }
+    }
+
+    let info = tokio::task::spawn_blocking(move || inspect_image(&path))
+        .await
+        .ok()??;
+    if let Ok(data) = serde_json::to_vec(&info) {
+        let _ = tokio::fs::write(&info_path, data).await;
+    }
+    Some(info)
+}
+
+/// Store generated content as a new media file, returning its path relative to `target`
+pub(crate) async fn store_file(target: &Path, name: &str, data: &[u8]) -> io::Result<String> {
+    let id = get_file_id();
+    let dir = target.join(&id);
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::write(dir.join(name), data).await?;
+    Ok(format!("{id}/{name}"))
 }
 
 /// Create a filter that receives files and stores them
@@ -83,3 +225,29 @@ pub fn create_upload_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
 pub fn create_media_filter(res: GlobalRes) -> BoxedFilter<(impl Reply,)> {
     warp::fs::dir(res.config.media_root.to_owned()).boxed()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_media_stays_in_root() {
+        let root = Path::new("/srv/media");
+        assert_eq!(
+            resolve_media(root, "/media/123-0/photo.png"),
+            Some(root.join("123-0/photo.png"))
+        );
+        for href in [
+            "/media/../secret",
+            "/media/123-0/../../secret",
+            "/media/./123-0/photo.png",
+            "/media//etc/passwd",
+            "/media/",
+            "/other/123-0/photo.png",
+            "media/123-0/photo.png",
+            "https://example.com/media/photo.png",
+        ] {
+            assert_eq!(resolve_media(root, href), None, "{href} was accepted");
+        }
+    }
+}
@@ -15,6 +15,10 @@ impl CounterU64 {
         Self(0.into())
     }
 
+    pub fn starting_at(value: u64) -> Self {
+        Self(value.into())
+    }
+
     pub fn next(&self) -> u64 {
         self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
@@ -50,3 +54,80 @@ pub trait IterExt: Iterator {
 }
 
 impl<T: Iterator> IterExt for T {}
+
+/// Apply a JSON merge patch (RFC 7396) to a value in place
+pub fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    use serde_json::Value;
+
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patched(mut target: serde_json::Value, patch: serde_json::Value) -> serde_json::Value {
+        merge_patch(&mut target, &patch);
+        target
+    }
+
+    #[test]
+    fn null_deletes_key() {
+        let result = patched(json!({ "a": 1, "b": 2 }), json!({ "a": null }));
+        assert_eq!(result, json!({ "b": 2 }));
+    }
+
+    #[test]
+    fn null_for_missing_key_is_ignored() {
+        let result = patched(json!({ "a": 1 }), json!({ "b": null }));
+        assert_eq!(result, json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn nested_objects_merge() {
+        let result = patched(
+            json!({ "stroke": { "width": 1, "color": "#000000ff" }, "text": "a" }),
+            json!({ "stroke": { "width": 2 } }),
+        );
+        assert_eq!(
+            result,
+            json!({ "stroke": { "width": 2, "color": "#000000ff" }, "text": "a" })
+        );
+    }
+
+    #[test]
+    fn arrays_are_replaced() {
+        let result = patched(json!({ "dash": [1, 2, 3] }), json!({ "dash": [4] }));
+        assert_eq!(result, json!({ "dash": [4] }));
+    }
+
+    #[test]
+    fn object_replaces_scalar() {
+        let result = patched(json!({ "a": 1 }), json!({ "a": { "b": 2, "c": null } }));
+        assert_eq!(result, json!({ "a": { "b": 2 } }));
+    }
+
+    #[test]
+    fn non_object_patch_replaces_target() {
+        assert_eq!(patched(json!({ "a": 1 }), json!("x")), json!("x"));
+    }
+}